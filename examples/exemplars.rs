@@ -0,0 +1,31 @@
+use anyhow::Result;
+use kagiyama::{prometheus::metrics::exemplar::CounterWithExemplar, AlwaysReady, Watcher};
+use tokio::time::{self, Duration};
+
+type TraceLabels = Vec<(String, String)>;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut watcher = Watcher::<AlwaysReady>::default();
+
+    let requests = CounterWithExemplar::<TraceLabels>::default();
+    {
+        let mut registry = watcher.metrics_registry();
+        registry.register("requests", "Simulated requests", requests.clone());
+    }
+
+    watcher.start_server("127.0.0.1:9090".parse()?).await?;
+
+    // Exemplars are only included when scraped with an OpenMetrics Accept header, e.g.:
+    // curl -H "Accept: application/openmetrics-text" http://127.0.0.1:9090/metrics
+    let mut interval = time::interval(Duration::from_secs(1));
+    for trace in 0u64.. {
+        interval.tick().await;
+        requests.inc_by(
+            1,
+            Some(vec![("trace_id".into(), format!("{:016x}", trace))]),
+        );
+    }
+
+    Ok(())
+}
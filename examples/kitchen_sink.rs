@@ -14,32 +14,36 @@ enum ReadinessConditions {
 #[tokio::main]
 async fn main() -> Result<()> {
     let mut watcher = Watcher::<ReadinessConditions>::default();
+    watcher.register_build_info(vec![("version".into(), env!("CARGO_PKG_VERSION").into())]);
+    watcher.set_version_info(vec![("version".into(), env!("CARGO_PKG_VERSION").into())])?;
 
-    watcher.start_server("127.0.0.1:9090".parse()?).await;
+    watcher.start_server("127.0.0.1:9090".parse()?).await?;
 
     // Simulate a subsystem starting up
-    let mut readiness_conditions = watcher.readiness_probe();
+    let readiness_conditions = watcher.readiness_probe();
     tokio::spawn(async move {
         time::sleep(Duration::from_secs(2)).await;
         readiness_conditions.mark_ready(ReadinessConditions::One);
     });
 
     // Simulate a subsystem starting up
-    let mut readiness_conditions = watcher.readiness_probe();
+    let readiness_conditions = watcher.readiness_probe();
     tokio::spawn(async move {
         time::sleep(Duration::from_secs(6)).await;
         readiness_conditions.mark_ready(ReadinessConditions::Two);
     });
 
     // Simulate an unstable condition
-    let mut readiness_conditions = watcher.readiness_probe();
+    let readiness_conditions = watcher.readiness_probe();
+    readiness_conditions.set_min_not_ready_duration(Duration::from_secs(10));
     tokio::spawn(async move {
         let mut interval = time::interval(Duration::from_secs(5));
         loop {
             interval.tick().await;
             readiness_conditions.mark_ready(ReadinessConditions::Three);
             interval.tick().await;
-            readiness_conditions.mark_not_ready(ReadinessConditions::Three);
+            readiness_conditions
+                .mark_not_ready_with_reason(ReadinessConditions::Three, "Simulated instability");
         }
     });
 
@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Result};
+use kagiyama::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    AlwaysReady, Watcher,
+};
+use std::{fs::File, io::BufReader};
+use tokio::time::{self, Duration};
+
+fn load_tls_config(cert_path: &str, key_path: &str) -> Result<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))?
+        .into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| anyhow!("No PKCS8 private key found in {}", key_path))?;
+
+    Ok(ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let cert_path = args
+        .next()
+        .ok_or_else(|| anyhow!("No certificate path given"))?;
+    let key_path = args
+        .next()
+        .ok_or_else(|| anyhow!("No private key path given"))?;
+
+    let mut watcher = Watcher::<AlwaysReady>::default();
+
+    watcher
+        .start_server_tls(
+            "127.0.0.1:9443".parse()?,
+            load_tls_config(&cert_path, &key_path)?,
+        )
+        .await?;
+
+    time::sleep(Duration::from_secs(300)).await;
+
+    Ok(())
+}
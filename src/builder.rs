@@ -0,0 +1,435 @@
+#[cfg(feature = "server")]
+use super::{
+    limit::{RequestLimits, ServerLimits},
+    service::{Config, LivenessResponse},
+};
+use super::{
+    readiness_probe::{ReadinessConditionSet, ReadinessProbe},
+    watcher::{Watcher, DEFAULT_UP_METRIC},
+};
+use anyhow::{bail, Result};
+#[cfg(feature = "server")]
+use hyper::{body::Bytes, StatusCode};
+#[cfg(feature = "server")]
+use ipnet::IpNet;
+use prometheus_client::registry::Registry;
+use serde::Serialize;
+use std::{
+    cmp::Eq,
+    hash::Hash,
+    marker::{Send, Sync},
+};
+#[cfg(feature = "server")]
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+pub struct WatcherBuilder<C: Hash + Eq + Send + Sync + Serialize> {
+    registry: Option<Registry>,
+    prefix: Option<String>,
+    constant_labels: Vec<(String, String)>,
+    up_metric: (String, String),
+    readiness_probe: ReadinessProbe<C>,
+    #[cfg(feature = "server")]
+    config: Config,
+    #[cfg(feature = "server")]
+    address: Option<SocketAddr>,
+}
+
+impl<C: 'static + Clone + ReadinessConditionSet + Hash + Eq + Sync + Send + Serialize> Default
+    for WatcherBuilder<C>
+{
+    fn default() -> Self {
+        Self::with_readiness_probe(ReadinessProbe::default())
+    }
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> WatcherBuilder<C> {
+    pub fn with_readiness_probe(readiness_probe: ReadinessProbe<C>) -> Self {
+        Self {
+            registry: None,
+            prefix: None,
+            constant_labels: Vec::new(),
+            up_metric: (DEFAULT_UP_METRIC.0.into(), DEFAULT_UP_METRIC.1.into()),
+            readiness_probe,
+            #[cfg(feature = "server")]
+            config: Config::default(),
+            #[cfg(feature = "server")]
+            address: None,
+        }
+    }
+
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn constant_labels(mut self, labels: Vec<(String, String)>) -> Self {
+        self.constant_labels = labels;
+        self
+    }
+
+    /// Registers the overall readiness gauge as `name` instead of `up`, which can be confused
+    /// with the `up` series Prometheus records for every scrape target.
+    pub fn up_metric(mut self, name: impl Into<String>, help: impl Into<String>) -> Self {
+        self.up_metric = (name.into(), help.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Watcher<C>> {
+        #[cfg(feature = "server")]
+        {
+            self.config.paths.validate()?;
+            if self.config.termination_signal_capacity == Some(0) {
+                bail!("Termination signal capacity must be greater than zero");
+            }
+            if self.config.paths.version.is_some() && self.config.version_info.is_empty() {
+                bail!("Version info must not be empty");
+            }
+        }
+
+        let customised = self.prefix.is_some() || !self.constant_labels.is_empty();
+        let registry = match (self.registry, customised) {
+            (Some(registry), false) => registry,
+            (Some(_), true) => {
+                bail!("A prefix or constant labels cannot be added to a provided registry")
+            }
+            (None, _) => {
+                let labels = self
+                    .constant_labels
+                    .into_iter()
+                    .map(|(k, v)| (k.into(), v.into()));
+                match self.prefix {
+                    Some(prefix) => Registry::with_prefix_and_labels(prefix, labels),
+                    None => Registry::with_labels(labels),
+                }
+            }
+        };
+
+        let watcher = Watcher::new(
+            registry,
+            self.readiness_probe,
+            (&self.up_metric.0, &self.up_metric.1),
+        );
+        #[cfg(feature = "server")]
+        let watcher = watcher.with_config(self.config, self.address);
+        Ok(watcher)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> WatcherBuilder<C> {
+    pub fn metrics_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.metrics = path.into();
+        self
+    }
+
+    pub fn ready_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.ready = path.into();
+        self
+    }
+
+    pub fn alive_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.alive = path.into();
+        self
+    }
+
+    pub fn startup_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.startup = path.into();
+        self
+    }
+
+    pub fn metrics_json_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.metrics_json = Some(path.into());
+        self
+    }
+
+    pub fn health_path(mut self, path: impl Into<String>) -> Self {
+        self.config.paths.health = Some(path.into());
+        self
+    }
+
+    pub fn version_info(mut self, info: Vec<(String, String)>) -> Self {
+        self.config
+            .paths
+            .version
+            .get_or_insert_with(|| "/version".into());
+        self.config.version_info = info;
+        self
+    }
+
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.config.bearer_token = Some(token.into());
+        self
+    }
+
+    pub fn cors_allowed_origins(mut self, origins: Vec<String>) -> Self {
+        self.config.cors_allowed_origins = origins;
+        self
+    }
+
+    pub fn allow_metrics_from(mut self, cidrs: Vec<IpNet>) -> Self {
+        self.config.metrics_allowlist = Some(cidrs);
+        self
+    }
+
+    pub fn liveness_response(
+        mut self,
+        status: StatusCode,
+        body: impl Into<Bytes>,
+        content_type: impl Into<String>,
+    ) -> Self {
+        self.config.liveness_response = LivenessResponse {
+            status,
+            body: body.into(),
+            content_type: content_type.into(),
+        };
+        self
+    }
+
+    pub fn liveness_check(mut self, check: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.config.liveness_checks.push(Arc::new(check));
+        self
+    }
+
+    pub fn watchdog_timeout(mut self, timeout: Duration) -> Self {
+        self.config.watchdog_timeout = Some(timeout);
+        self
+    }
+
+    pub fn ready_pretty(mut self, pretty: bool) -> Self {
+        self.config.ready_pretty = pretty;
+        self
+    }
+
+    pub fn ready_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.config.ready_content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn ready_retry_after(mut self, retry_after: Duration) -> Self {
+        self.config.ready_retry_after = Some(retry_after);
+        self
+    }
+
+    pub fn termination_signal_capacity(mut self, capacity: usize) -> Self {
+        self.config.termination_signal_capacity = Some(capacity);
+        self
+    }
+
+    pub fn server_limits(mut self, limits: ServerLimits) -> Self {
+        self.config.server_limits = limits;
+        self
+    }
+
+    pub fn access_log(mut self, enable: bool) -> Self {
+        self.config.access_log = enable;
+        self
+    }
+
+    pub fn request_limits(mut self, limits: RequestLimits) -> Self {
+        self.config.request_limits = limits;
+        self
+    }
+
+    pub fn reuse_address(mut self, reuse: bool) -> Self {
+        self.config.reuse_address = reuse;
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.config.reuse_port = reuse;
+        self
+    }
+
+    #[cfg(feature = "http2")]
+    pub fn http2(mut self, enable: bool) -> Self {
+        self.config.http2 = enable;
+        self
+    }
+
+    pub fn runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.config.runtime = Some(runtime);
+        self
+    }
+
+    pub fn request_timeout(mut self, path: impl Into<String>, timeout: Duration) -> Self {
+        self.config.request_timeouts.insert(path.into(), timeout);
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    pub fn disable_index(mut self) -> Self {
+        self.config.disable_index = true;
+        self
+    }
+
+    pub fn metrics_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.config.metrics_cache_ttl = ttl;
+        self
+    }
+
+    pub fn address(mut self, address: SocketAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AlwaysReady;
+    use prometheus_client::metrics::gauge::Gauge;
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_build() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .metrics_path("/internal/metrics")
+            .bearer_token("s3cret")
+            .address("127.0.0.1:0".parse().unwrap())
+            .build();
+        assert!(watcher.is_ok());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_build_invalid_paths() {
+        let watcher = Watcher::<AlwaysReady>::builder().ready_path("").build();
+        assert!(watcher.is_err());
+
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .ready_path("/health")
+            .alive_path("/health")
+            .build();
+        assert!(watcher.is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_build_termination_signal_capacity() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .termination_signal_capacity(4)
+            .build();
+        assert!(watcher.is_ok());
+
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .termination_signal_capacity(0)
+            .build();
+        assert!(watcher.is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_build_version_info() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .version_info(vec![("version".into(), "1.2.3".into())])
+            .build();
+        assert!(watcher.is_ok());
+
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .version_info(vec![])
+            .build();
+        assert!(watcher.is_err());
+    }
+
+    #[test]
+    fn test_build_constant_labels() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .constant_labels(vec![("instance".into(), "a".into())])
+            .build()
+            .unwrap();
+        assert!(watcher
+            .encode_metrics()
+            .unwrap()
+            .contains("\nup{instance=\"a\"} 1\n"));
+
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .with_registry(Registry::default())
+            .constant_labels(vec![("instance".into(), "a".into())])
+            .build();
+        assert!(watcher.is_err());
+    }
+
+    #[test]
+    fn test_build_prefix() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .prefix("myservice")
+            .constant_labels(vec![("instance".into(), "a".into())])
+            .build()
+            .unwrap();
+        assert!(watcher
+            .encode_metrics()
+            .unwrap()
+            .contains("\nmyservice_up{instance=\"a\"} 1\n"));
+
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .with_registry(Registry::default())
+            .prefix("myservice")
+            .build();
+        assert!(watcher.is_err());
+    }
+
+    #[test]
+    fn test_build_up_metric() {
+        let watcher = Watcher::<AlwaysReady>::builder()
+            .up_metric("service_ready", "Service readiness")
+            .build()
+            .unwrap();
+        let metrics = watcher.encode_metrics().unwrap();
+        assert!(metrics.contains("# HELP service_ready Service readiness"));
+        assert!(metrics.contains("\nservice_ready 1\n"));
+        assert!(!metrics.contains("\nup "));
+
+        assert!(watcher
+            .try_register("up", "Something else", Gauge::<i64>::default())
+            .is_ok());
+        assert!(watcher
+            .try_register("service_ready", "Clash", Gauge::<i64>::default())
+            .is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_configured_address() {
+        let mut watcher = Watcher::<AlwaysReady>::builder()
+            .address("127.0.0.1:0".parse().unwrap())
+            .build()
+            .unwrap();
+
+        let (address, handle) = watcher.start().await.unwrap();
+        assert_ne!(address.port(), 0);
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_build_dynamic_conditions() {
+        let readiness_probe = ReadinessProbe::<String>::dynamic();
+        readiness_probe.register_condition("database".to_string());
+
+        let watcher = WatcherBuilder::with_readiness_probe(readiness_probe)
+            .build()
+            .unwrap();
+        assert!(!watcher.readiness_probe().is_ready());
+
+        watcher.readiness_probe().mark_ready("database".to_string());
+        assert!(watcher.readiness_probe().is_ready());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_without_address() {
+        let mut watcher = Watcher::<AlwaysReady>::builder().build().unwrap();
+        assert!(watcher.start().await.is_err());
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(feature = "tls")]
+use crate::tls::ClientCertificates;
+use hyper::{server::conn::AddrStream, Body, Request};
+use std::net::SocketAddr;
+
+// Details of the connection a request arrived on, made available to custom route handlers as
+// request extensions
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionInfo {
+    pub(crate) remote_addr: Option<SocketAddr>,
+    #[cfg(feature = "tls")]
+    pub(crate) client_certificates: Option<ClientCertificates>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(remote_addr: Option<SocketAddr>) -> Self {
+        Self {
+            remote_addr,
+            #[cfg(feature = "tls")]
+            client_certificates: None,
+        }
+    }
+
+    pub(crate) fn insert_extensions(&self, req: &mut Request<Body>) {
+        if let Some(remote_addr) = self.remote_addr {
+            req.extensions_mut().insert(remote_addr);
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(client_certificates) = &self.client_certificates {
+            req.extensions_mut().insert(client_certificates.clone());
+        }
+    }
+}
+
+pub(crate) trait Connection {
+    fn info(&self) -> ConnectionInfo;
+}
+
+impl Connection for AddrStream {
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo::new(Some(self.remote_addr()))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl<C: Connection> Connection for tokio_rustls::server::TlsStream<C> {
+    fn info(&self) -> ConnectionInfo {
+        let (stream, session) = self.get_ref();
+        let mut info = stream.info();
+        info.client_certificates = session
+            .peer_certificates()
+            .map(|certificates| ClientCertificates(certificates.to_vec()));
+        info
+    }
+}
+
+#[cfg(all(unix, feature = "uds"))]
+impl Connection for tokio::net::UnixStream {
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo::default()
+    }
+}
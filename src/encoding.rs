@@ -0,0 +1,34 @@
+use prometheus_client::{encoding::text::encode, registry::Registry};
+use std::fmt;
+
+/// Encodes `registry` in the OpenMetrics text format.
+///
+/// This is the encoding used for the metrics endpoint, and the single place the crate calls the
+/// [`prometheus_client`](crate::prometheus) encoder.
+pub fn encode_registry(registry: &Registry) -> Result<String, fmt::Error> {
+    let mut buffer = String::new();
+    encode_registry_into(&mut buffer, registry)?;
+    Ok(buffer)
+}
+
+/// Appends the encoding of `registry` to `buffer`, allowing the buffer to be reused.
+pub(crate) fn encode_registry_into(buffer: &mut String, registry: &Registry) -> fmt::Result {
+    encode(buffer, registry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::metrics::gauge::Gauge;
+
+    #[test]
+    fn test_encode_registry() {
+        let mut registry = Registry::default();
+        registry.register("things", "Some things", Gauge::<i64>::default());
+
+        assert_eq!(
+            encode_registry(&registry).unwrap(),
+            "# HELP things Some things.\n# TYPE things gauge\nthings 0\n# EOF\n"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::{error::Error, fmt};
+
+/// Returned when a metric is registered under a name that is already in use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlreadyRegistered(pub String);
+
+impl fmt::Display for AlreadyRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Metric \"{}\" is already registered", self.0)
+    }
+}
+
+impl Error for AlreadyRegistered {}
+
+/// Returned by [`Watcher::stop_server`](crate::Watcher::stop_server) when it can not stop
+/// anything.
+#[cfg(feature = "server")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopError {
+    /// No server or pusher started by the watcher is running.
+    NoServerRunning,
+}
+
+#[cfg(feature = "server")]
+impl fmt::Display for StopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoServerRunning => write!(f, "No server is running"),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl Error for StopError {}
@@ -1,7 +1,68 @@
+// Emits a structured `tracing` event with the given fields when that feature is enabled, or the
+// equivalent `log` record otherwise
+#[cfg_attr(not(feature = "server"), allow(unused_macros))]
+macro_rules! event {
+    ($level:ident, { $($field:tt)* }, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($field)* $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        log::$level!($($arg)+);
+    }};
+}
+
+mod builder;
+#[cfg(feature = "server")]
+mod connection;
+mod encoding;
+mod error;
+#[cfg(feature = "server")]
+mod limit;
+#[cfg(feature = "server")]
+mod metrics_json;
+#[cfg(feature = "process")]
+mod process;
 mod readiness_probe;
+#[cfg(feature = "server")]
+mod service;
+#[cfg(feature = "statsd")]
+mod statsd;
+// Also used by the crate's own tests
+#[cfg(any(feature = "test-util", all(test, feature = "server")))]
+#[cfg_attr(not(feature = "test-util"), allow(dead_code))]
+mod test_util;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(all(unix, feature = "uds"))]
+mod uds;
+#[cfg(feature = "server")]
+mod watchdog;
 mod watcher;
 
-pub use readiness_probe::{AlwaysReady, ReadinessProbe};
+pub use builder::WatcherBuilder;
+pub use encoding::encode_registry;
+pub use error::AlreadyRegistered;
+#[cfg(feature = "server")]
+pub use error::StopError;
+#[cfg(feature = "server")]
+pub use limit::{RequestLimits, ServerLimits};
+pub use readiness_probe::{
+    AlwaysReady, ConditionDescription, EmptyPolicy, ReadinessConditionSet, ReadinessProbe,
+    ReadinessState,
+};
+#[cfg(feature = "server")]
+pub use service::{BoxedService, WatcherService};
+#[cfg(feature = "test-util")]
+pub use test_util::TestWatcher;
+#[cfg(feature = "tls")]
+pub use tls::ClientCertificates;
+#[cfg(feature = "server")]
+pub use watchdog::Watchdog;
+#[cfg(feature = "server")]
+pub use watcher::ServerGuard;
 pub use watcher::Watcher;
 
+#[cfg(feature = "server")]
+pub use ipnet;
 pub use prometheus_client as prometheus;
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
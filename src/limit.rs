@@ -0,0 +1,167 @@
+use super::connection::{Connection, ConnectionInfo};
+use hyper::server::accept::Accept;
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Limits applied to each server started by a [`Watcher`](crate::Watcher).
+///
+/// By default at most 256 concurrent connections are accepted and request headers must be
+/// received within 30 seconds, fields set to `None` are unlimited.
+#[derive(Clone, Debug)]
+pub struct ServerLimits {
+    /// Connections accepted beyond this many concurrent connections are closed immediately.
+    pub max_connections: Option<usize>,
+    pub keep_alive: bool,
+    /// Connections that do not send a complete request head within this time are closed.
+    pub header_read_timeout: Option<Duration>,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: Some(256),
+            keep_alive: true,
+            header_read_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Limits applied to each request received by servers started by a [`Watcher`](crate::Watcher).
+///
+/// By default buffers are limited to 64 KiB and bodies to 1 MiB, fields set to `None` are
+/// unlimited.
+#[derive(Clone, Debug)]
+pub struct RequestLimits {
+    /// Bounds the memory used to buffer each connection, and so the size of request heads.
+    /// Values below 8 KiB are raised to 8 KiB.
+    pub max_buf_size: Option<usize>,
+    /// Requests with a larger body, whether declared by `Content-Length` or streamed, receive 413
+    /// Payload Too Large. Bodies without a `Content-Length` are read in full before handling.
+    pub max_body_size: Option<u64>,
+    /// Requests with more headers receive 431 Request Header Fields Too Large. Hyper rejects
+    /// requests with more than 100 headers regardless of this limit.
+    pub max_headers: Option<usize>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_buf_size: Some(64 * 1024),
+            max_body_size: Some(1024 * 1024),
+            max_headers: None,
+        }
+    }
+}
+
+pub(crate) struct LimitedIncoming<I> {
+    incoming: I,
+    max_connections: Option<usize>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl<I> LimitedIncoming<I> {
+    pub(crate) fn new(incoming: I, max_connections: Option<usize>) -> Self {
+        Self {
+            incoming,
+            max_connections,
+            connections: Arc::default(),
+        }
+    }
+}
+
+impl<I: Accept + Unpin> Accept for LimitedIncoming<I> {
+    type Conn = LimitedConn<I::Conn>;
+    type Error = I::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            let conn = match ready!(Pin::new(&mut this.incoming).poll_accept(cx)) {
+                Some(Ok(conn)) => conn,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+
+            let count = this.connections.fetch_add(1, Ordering::Relaxed);
+            let conn = LimitedConn {
+                conn,
+                connections: this.connections.clone(),
+            };
+
+            match this.max_connections {
+                Some(max) if count >= max => log::debug!("Connection limit reached"),
+                _ => return Poll::Ready(Some(Ok(conn))),
+            }
+        }
+    }
+}
+
+pub(crate) struct LimitedConn<C> {
+    conn: C,
+    connections: Arc<AtomicUsize>,
+}
+
+impl<C> Drop for LimitedConn<C> {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<C: Connection> Connection for LimitedConn<C> {
+    fn info(&self) -> ConnectionInfo {
+        self.conn.info()
+    }
+}
+
+impl<C: AsyncRead + Unpin> AsyncRead for LimitedConn<C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_read(cx, buf)
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for LimitedConn<C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().conn).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().conn).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.conn.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_shutdown(cx)
+    }
+}
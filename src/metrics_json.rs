@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// Suffixes that the text format appends to a metric family name for its individual samples
+const SAMPLE_SUFFIXES: [&str; 7] = [
+    "", "_total", "_bucket", "_count", "_sum", "_created", "_info",
+];
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Sample<'a> {
+    pub(crate) name: &'a str,
+    #[serde(rename = "type")]
+    pub(crate) metric_type: &'a str,
+    pub(crate) labels: BTreeMap<&'a str, String>,
+    pub(crate) value: f64,
+}
+
+/// Converts metrics encoded in the text format into a JSON array of samples.
+pub(crate) fn encode(text: &str) -> Result<String> {
+    Ok(serde_json::to_string(&parse(text)?)?)
+}
+
+/// Parses the individual samples from metrics encoded in the text format.
+pub(crate) fn parse(text: &str) -> Result<Vec<Sample<'_>>> {
+    let mut types = HashMap::new();
+    let mut samples = Vec::new();
+
+    for line in text.lines() {
+        if let Some(family) = line.strip_prefix("# TYPE ") {
+            if let Some((name, metric_type)) = family.split_once(' ') {
+                types.insert(name, metric_type);
+            }
+        } else if !line.is_empty() && !line.starts_with('#') {
+            samples.push(parse_sample(line, &types)?);
+        }
+    }
+
+    Ok(samples)
+}
+
+fn parse_sample<'a>(line: &'a str, types: &HashMap<&'a str, &'a str>) -> Result<Sample<'a>> {
+    let invalid = || anyhow!("Invalid sample \"{}\"", line);
+
+    let name_end = line.find(['{', ' ']).ok_or_else(invalid)?;
+    let name = &line[..name_end];
+    let mut rest = &line[name_end..];
+
+    let mut labels = BTreeMap::new();
+    if let Some(mut remaining) = rest.strip_prefix('{') {
+        loop {
+            remaining = remaining.trim_start_matches(',');
+            if let Some(after) = remaining.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (key, quoted) = remaining.split_once("=\"").ok_or_else(invalid)?;
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next().ok_or_else(invalid)? {
+                    (i, '"') => break i,
+                    (_, '\\') => match chars.next().ok_or_else(invalid)?.1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+
+            labels.insert(key, value);
+            remaining = &quoted[end + 1..];
+        }
+    }
+
+    let value = rest
+        .split_whitespace()
+        .next()
+        .ok_or_else(invalid)?
+        .parse()
+        .map_err(|_| invalid())?;
+
+    let metric_type = SAMPLE_SUFFIXES
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix))
+        .find_map(|family| types.get(family))
+        .copied()
+        .unwrap_or("unknown");
+
+    Ok(Sample {
+        name,
+        metric_type,
+        labels,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sample() {
+        let types = HashMap::from([("requests", "counter"), ("latency", "histogram")]);
+
+        assert_eq!(
+            parse_sample("requests_total 3", &types).unwrap(),
+            Sample {
+                name: "requests_total",
+                metric_type: "counter",
+                labels: BTreeMap::new(),
+                value: 3.0,
+            }
+        );
+
+        assert_eq!(
+            parse_sample(
+                "latency_bucket{path=\"/a \\\"b\\\"\",le=\"+Inf\"} 1.5 # {} 1",
+                &types
+            )
+            .unwrap(),
+            Sample {
+                name: "latency_bucket",
+                metric_type: "histogram",
+                labels: BTreeMap::from([("path", "/a \"b\"".into()), ("le", "+Inf".into())]),
+                value: 1.5,
+            }
+        );
+
+        assert_eq!(
+            parse_sample("other 1", &types).unwrap().metric_type,
+            "unknown"
+        );
+        assert!(parse_sample("broken{path=\"/\"", &types).is_err());
+        assert!(parse_sample("broken nope", &types).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let text = "# HELP up Up\n# TYPE up gauge\nup 1\n# EOF\n";
+        assert_eq!(
+            encode(text).unwrap(),
+            r#"[{"name":"up","type":"gauge","labels":{},"value":1.0}]"#
+        );
+        assert_eq!(encode("").unwrap(), "[]");
+    }
+}
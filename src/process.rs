@@ -0,0 +1,199 @@
+use prometheus_client::registry::Registry;
+
+#[cfg(target_os = "linux")]
+pub(crate) fn register(registry: &mut Registry) {
+    registry.register_collector(Box::new(linux::ProcessCollector::new()));
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn register(_registry: &mut Registry) {
+    log::debug!("Process metrics are not supported on this platform");
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use prometheus_client::{
+        collector::Collector,
+        metrics::{counter::ConstCounter, gauge::ConstGauge},
+        registry::{Descriptor, LocalMetric},
+        MaybeOwned,
+    };
+    use std::{borrow::Cow, fs};
+
+    #[derive(Debug)]
+    pub(crate) struct ProcessCollector {
+        clock_ticks: f64,
+        page_size: u64,
+    }
+
+    impl ProcessCollector {
+        pub(crate) fn new() -> Self {
+            // SAFETY: sysconf has no preconditions and only reads system configuration
+            let (clock_ticks, page_size) = unsafe {
+                (
+                    libc::sysconf(libc::_SC_CLK_TCK),
+                    libc::sysconf(libc::_SC_PAGESIZE),
+                )
+            };
+
+            Self {
+                clock_ticks: clock_ticks as f64,
+                page_size: page_size as u64,
+            }
+        }
+    }
+
+    impl Collector for ProcessCollector {
+        fn collect<'a>(
+            &'a self,
+        ) -> Box<
+            dyn Iterator<Item = (Cow<'a, Descriptor>, MaybeOwned<'a, Box<dyn LocalMetric>>)> + 'a,
+        > {
+            let mut metrics = Vec::new();
+            let mut push = |name: &str, help: &str, metric: Box<dyn LocalMetric>| {
+                metrics.push((
+                    Cow::Owned(Descriptor::new(name, help, None, None, vec![])),
+                    MaybeOwned::Owned(metric),
+                ));
+            };
+
+            match fs::read_to_string("/proc/self/stat")
+                .ok()
+                .and_then(|stat| parse_stat(&stat))
+            {
+                Some(stat) => {
+                    push(
+                        "process_cpu_seconds",
+                        "Total user and system CPU time spent in seconds",
+                        Box::new(ConstCounter::new(
+                            (stat.utime + stat.stime) as f64 / self.clock_ticks,
+                        )),
+                    );
+                    push(
+                        "process_virtual_memory_bytes",
+                        "Virtual memory size in bytes",
+                        Box::new(ConstGauge::new(stat.vsize as i64)),
+                    );
+                    push(
+                        "process_resident_memory_bytes",
+                        "Resident memory size in bytes",
+                        Box::new(ConstGauge::new((stat.rss * self.page_size) as i64)),
+                    );
+                    push(
+                        "process_threads",
+                        "Number of OS threads in the process",
+                        Box::new(ConstGauge::new(stat.threads as i64)),
+                    );
+                }
+                None => log::debug!("Failed to read /proc/self/stat"),
+            }
+
+            match fs::read_dir("/proc/self/fd") {
+                Ok(fds) => push(
+                    "process_open_fds",
+                    "Number of open file descriptors",
+                    Box::new(ConstGauge::new(fds.count() as i64)),
+                ),
+                Err(e) => log::debug!("Failed to read /proc/self/fd: {}", e),
+            }
+
+            if let Some(max_fds) = fs::read_to_string("/proc/self/limits")
+                .ok()
+                .and_then(|limits| parse_max_fds(&limits))
+            {
+                push(
+                    "process_max_fds",
+                    "Maximum number of open file descriptors",
+                    Box::new(ConstGauge::new(max_fds as i64)),
+                );
+            }
+
+            Box::new(metrics.into_iter())
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub(super) struct Stat {
+        pub(super) utime: u64,
+        pub(super) stime: u64,
+        pub(super) threads: u64,
+        pub(super) vsize: u64,
+        pub(super) rss: u64,
+    }
+
+    pub(super) fn parse_stat(stat: &str) -> Option<Stat> {
+        // The command name may contain spaces or parentheses, so start after the last one, at
+        // the process state (field 3 in proc(5))
+        let fields: Vec<&str> = stat
+            .get(stat.rfind(')')? + 1..)?
+            .split_whitespace()
+            .collect();
+        let field = |n: usize| fields.get(n - 3)?.parse().ok();
+
+        Some(Stat {
+            utime: field(14)?,
+            stime: field(15)?,
+            threads: field(20)?,
+            vsize: field(23)?,
+            rss: field(24)?,
+        })
+    }
+
+    pub(super) fn parse_max_fds(limits: &str) -> Option<u64> {
+        limits
+            .lines()
+            .find_map(|line| line.strip_prefix("Max open files"))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::linux::*;
+    use super::*;
+    use crate::encoding::encode_registry;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "1234 (my (weird) app) S 1 1234 1234 0 -1 4194560 1034 0 0 0 \
+                    250 50 0 0 20 0 8 0 12345 104857600 2560 18446744073709551615";
+        assert_eq!(
+            parse_stat(stat),
+            Some(Stat {
+                utime: 250,
+                stime: 50,
+                threads: 8,
+                vsize: 104857600,
+                rss: 2560,
+            })
+        );
+
+        assert_eq!(parse_stat("1234 (app) S 1"), None);
+    }
+
+    #[test]
+    fn test_parse_max_fds() {
+        let limits = "Limit                     Soft Limit           Hard Limit           Units     \n\
+                      Max processes             127422               127422               processes \n\
+                      Max open files            1024                 524288               files     \n";
+        assert_eq!(parse_max_fds(limits), Some(1024));
+
+        let limits =
+            "Max open files            unlimited            unlimited            files     \n";
+        assert_eq!(parse_max_fds(limits), None);
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = Registry::default();
+        register(&mut registry);
+
+        let buffer = encode_registry(&registry).unwrap();
+        assert!(buffer.contains("process_cpu_seconds_total "));
+        assert!(buffer.contains("process_resident_memory_bytes "));
+        assert!(buffer.contains("process_open_fds "));
+    }
+}
@@ -1,11 +1,25 @@
-use prometheus_client::metrics::gauge::Gauge;
-use serde::Serialize;
+use anyhow::{bail, Result};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, EncodeMetric, MetricEncoder},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::{ConstGauge, Gauge},
+        MetricType,
+    },
+};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use std::{
     cmp::Eq,
     collections::HashMap,
+    fmt,
     hash::Hash,
     marker::{Send, Sync},
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -13,74 +27,863 @@ use strum_macros::EnumIter;
 #[derive(Clone, Serialize, PartialEq, Eq, Hash, EnumIter)]
 pub enum AlwaysReady {}
 
+/// The fixed set of conditions a [`ReadinessProbe`] is seeded with by [`ReadinessProbe::default`].
+///
+/// This is implemented for every type implementing [`IntoEnumIterator`], and can be implemented
+/// manually for other types.
+pub trait ReadinessConditionSet: Sized {
+    fn all() -> Vec<Self>;
+}
+
+impl<C: IntoEnumIterator> ReadinessConditionSet for C {
+    fn all() -> Vec<Self> {
+        C::iter().collect()
+    }
+}
+
+/// Provides a human readable description of a readiness condition, used by
+/// [`ReadinessProbe::with_descriptions`].
+pub trait ConditionDescription {
+    fn description(&self) -> &str;
+}
+
+/// Whether a probe without any (required) conditions is considered ready.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyPolicy {
+    #[default]
+    Ready,
+    NotReady,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum ReadinessState {
+    Ready,
+    Degraded,
+    NotReady,
+}
+
+impl ReadinessState {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Ready | Self::Degraded)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConditionState {
+    pub(crate) state: ReadinessState,
+    pub(crate) reason: Option<String>,
+    pub(crate) last_changed: SystemTime,
+    pub(crate) last_updated: Instant,
+    pub(crate) expires: Option<Instant>,
+    pub(crate) held_until: Option<Instant>,
+    observed_ready: bool,
+    pub(crate) optional: bool,
+    pub(crate) weight: f64,
+    pub(crate) description: Option<String>,
+}
+
+impl Default for ConditionState {
+    fn default() -> Self {
+        Self {
+            state: ReadinessState::NotReady,
+            reason: None,
+            last_changed: SystemTime::now(),
+            last_updated: Instant::now(),
+            expires: None,
+            held_until: None,
+            observed_ready: false,
+            optional: false,
+            weight: 1.0,
+            description: None,
+        }
+    }
+}
+
+impl ConditionState {
+    fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| Instant::now() >= expires)
+    }
+
+    fn is_held(&self) -> bool {
+        self.held_until
+            .is_some_and(|held_until| Instant::now() < held_until)
+    }
+
+    pub(crate) fn state(&self) -> ReadinessState {
+        match self.is_expired() || self.is_held() {
+            true => ReadinessState::NotReady,
+            false => self.state,
+        }
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.state().is_ready()
+    }
+
+    // Records the readiness last accounted for, returning whether it has changed since, which
+    // can happen without the condition being set when it expires or its hold ends
+    fn observe(&mut self) -> bool {
+        let ready = self.is_ready();
+        std::mem::replace(&mut self.observed_ready, ready) != ready
+    }
+}
+
+impl Serialize for ConditionState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let reason = match (&self.reason, self.is_expired(), self.is_held()) {
+            (Some(reason), _, _) => Some(reason.as_str()),
+            (None, true, _) => Some("Readiness expired"),
+            (None, false, true) => Some("Recently not ready"),
+            (None, false, false) => None,
+        };
+        let last_changed = self
+            .last_changed
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        let mut state = serializer.serialize_struct("ConditionState", 6)?;
+        state.serialize_field("ready", &self.is_ready())?;
+        state.serialize_field("state", &self.state())?;
+        match reason {
+            Some(reason) => state.serialize_field("reason", reason)?,
+            None => state.skip_field("reason")?,
+        }
+        state.serialize_field("last_changed", &last_changed)?;
+        match self.optional {
+            true => state.serialize_field("optional", &true)?,
+            false => state.skip_field("optional")?,
+        }
+        match &self.description {
+            Some(description) => state.serialize_field("description", description)?,
+            None => state.skip_field("description")?,
+        }
+        state.end()
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub(crate) struct ConditionLabels {
+    condition: String,
+}
+
+impl ConditionLabels {
+    fn new<C: Serialize>(condition: &C) -> Self {
+        let condition = match serde_json::to_value(condition) {
+            Ok(serde_json::Value::String(name)) => name,
+            Ok(value) => value.to_string(),
+            Err(e) => {
+                log::warn!("Failed to serialize readiness condition: {}", e);
+                String::new()
+            }
+        };
+        Self { condition }
+    }
+}
+
+// Body of the readiness endpoint, listing the required conditions which are not ready ahead of
+// the state of every condition
+#[derive(Serialize)]
+struct ReadinessReport<'a, C: Hash + Eq + Serialize> {
+    ready: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+    not_ready: Vec<String>,
+    conditions: &'a HashMap<C, ConditionState>,
+}
+
+// Reports the seconds since each condition was last set, computed each time it is encoded
+pub(crate) struct ConditionAges<C> {
+    conditions: Arc<RwLock<HashMap<C, ConditionState>>>,
+}
+
+impl<C> fmt::Debug for ConditionAges<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConditionAges").finish_non_exhaustive()
+    }
+}
+
+impl<C: Serialize> EncodeMetric for ConditionAges<C> {
+    fn encode(&self, mut encoder: MetricEncoder<'_, '_>) -> Result<(), fmt::Error> {
+        let conditions = self
+            .conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (condition, state) in conditions.iter() {
+            let age = ConstGauge::new(state.last_updated.elapsed().as_secs_f64());
+            age.encode(encoder.encode_family(&ConditionLabels::new(condition))?)?;
+        }
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        MetricType::Gauge
+    }
+}
+
+type TransitionCallback = Arc<dyn Fn(bool) + Send + Sync>;
+
+// Allows probes with differing condition types to be held as children of another probe
+trait ReadyCheck: Send + Sync {
+    fn is_ready(&self) -> bool;
+}
+
+impl<C: Hash + Eq + Send + Sync + Serialize> ReadyCheck for ReadinessProbe<C> {
+    fn is_ready(&self) -> bool {
+        ReadinessProbe::is_ready(self)
+    }
+}
+
 #[derive(Clone)]
 pub struct ReadinessProbe<C: Sync + Send> {
-    pub(crate) conditions: Arc<RwLock<HashMap<C, bool>>>,
+    pub(crate) conditions: Arc<RwLock<HashMap<C, ConditionState>>>,
     pub(crate) up: Gauge<i64>,
+    pub(crate) score: Gauge<f64, AtomicU64>,
+    pub(crate) degraded: Gauge<i64>,
+    pub(crate) condition_ready: Family<ConditionLabels, Gauge<i64>>,
+    pub(crate) condition_transitions: Family<ConditionLabels, Counter>,
+    pub(crate) conditions_total: Gauge<i64>,
+    pub(crate) conditions_ready: Gauge<i64>,
+    started: Arc<AtomicBool>,
+    warned_empty: Arc<AtomicBool>,
+    draining: Arc<AtomicBool>,
+    min_not_ready: Arc<RwLock<Option<Duration>>>,
+    quorum: Arc<RwLock<Option<usize>>>,
+    score_threshold: Arc<RwLock<Option<f64>>>,
+    empty_policy: Arc<RwLock<EmptyPolicy>>,
+    transition_callbacks: Arc<RwLock<Vec<TransitionCallback>>>,
+    children: Arc<RwLock<Vec<Box<dyn ReadyCheck>>>>,
 }
 
-impl<C: IntoEnumIterator + Hash + Eq + Send + Sync + Serialize> Default for ReadinessProbe<C> {
+impl<C: ReadinessConditionSet + Hash + Eq + Send + Sync + Serialize> Default for ReadinessProbe<C> {
     fn default() -> Self {
-        let conditions = Arc::new(RwLock::new(C::iter().map(|c| (c, false)).collect()));
-        let up = Gauge::<i64>::default();
+        Self::with_conditions(
+            C::all()
+                .into_iter()
+                .map(|c| (c, ConditionState::default()))
+                .collect(),
+        )
+    }
+}
+
+impl<C: ConditionDescription + ReadinessConditionSet + Hash + Eq + Send + Sync + Serialize>
+    ReadinessProbe<C>
+{
+    pub fn with_descriptions() -> Self {
+        Self::with_conditions(
+            C::all()
+                .into_iter()
+                .map(|c| {
+                    let state = ConditionState {
+                        description: Some(c.description().to_owned()),
+                        ..Default::default()
+                    };
+                    (c, state)
+                })
+                .collect(),
+        )
+    }
+}
 
-        let mut probe = Self { conditions, up };
-        probe.update_up_metric();
+impl<C: Hash + Eq + Send + Sync + Serialize> ReadinessProbe<C> {
+    pub fn dynamic() -> Self {
+        Self::with_conditions(HashMap::new())
+    }
+
+    fn with_conditions(conditions: HashMap<C, ConditionState>) -> Self {
+        let probe = Self {
+            conditions: Arc::new(RwLock::new(conditions)),
+            up: Gauge::default(),
+            score: Gauge::default(),
+            degraded: Gauge::default(),
+            condition_ready: Family::default(),
+            condition_transitions: Family::default(),
+            conditions_total: Gauge::default(),
+            conditions_ready: Gauge::default(),
+            started: Default::default(),
+            warned_empty: Default::default(),
+            draining: Default::default(),
+            min_not_ready: Default::default(),
+            quorum: Default::default(),
+            score_threshold: Default::default(),
+            empty_policy: Default::default(),
+            transition_callbacks: Default::default(),
+            children: Default::default(),
+        };
+        probe.update_metrics();
 
         probe
     }
-}
 
-impl<C: IntoEnumIterator + Hash + Eq + Send + Sync + Serialize> ReadinessProbe<C> {
-    pub(crate) fn is_ready(&self) -> bool {
-        let conditions = self.conditions.read().unwrap();
-        if conditions.is_empty() {
-            true
-        } else {
-            conditions.iter().map(|(_, v)| v).all(|v| *v)
+    /// Conditions that have expired, or come out of being held not ready, since readiness was
+    /// last checked have their transitions counted and callbacks fired here.
+    pub fn is_ready(&self) -> bool {
+        let observed = self.observe();
+        let ready = self.ready();
+        if observed || ready != (self.up.get() == 1) {
+            self.update_metrics();
+        }
+        ready
+    }
+
+    fn ready(&self) -> bool {
+        !self.draining.load(Ordering::Relaxed)
+            && self.conditions_ready()
+            && self
+                .children
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .all(|child| child.is_ready())
+    }
+
+    fn conditions_ready(&self) -> bool {
+        let conditions = self
+            .conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut conditions = conditions.values().filter(|c| !c.optional).peekable();
+
+        if conditions.peek().is_none() {
+            return *self
+                .empty_policy
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                == EmptyPolicy::Ready;
+        }
+
+        if let Some(threshold) = *self
+            .score_threshold
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+        {
+            return weighted_score(conditions) >= threshold;
+        }
+
+        match *self.quorum.read().unwrap_or_else(PoisonError::into_inner) {
+            Some(quorum) => conditions.filter(|c| c.is_ready()).count() >= quorum,
+            None => conditions.all(ConditionState::is_ready),
+        }
+    }
+
+    /// Returns the total weight of ready conditions as a fraction of the total weight of all
+    /// required conditions. If every required condition has a weight of zero they count equally,
+    /// and without any required conditions the score is 1.0.
+    pub fn score(&self) -> f64 {
+        weighted_score(
+            self.conditions
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .values()
+                .filter(|c| !c.optional),
+        )
+    }
+
+    pub fn is_condition_ready(&self, condition: &C) -> Option<bool> {
+        self.conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(condition)
+            .map(ConditionState::is_ready)
+    }
+
+    pub fn conditions_snapshot(&self) -> HashMap<C, bool>
+    where
+        C: Clone,
+    {
+        self.conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(condition, state)| (condition.clone(), state.is_ready()))
+            .collect()
+    }
+
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn report_json(&self, ready: bool, pretty: bool) -> serde_json::Result<String> {
+        let conditions = self
+            .conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut not_ready: Vec<String> = conditions
+            .iter()
+            .filter(|(_, state)| !state.optional && !state.is_ready())
+            .map(|(condition, _)| ConditionLabels::new(condition).condition)
+            .collect();
+        not_ready.sort();
+
+        let report = ReadinessReport {
+            ready,
+            draining: self.draining.load(Ordering::Relaxed),
+            not_ready,
+            conditions: &conditions,
+        };
+        match pretty {
+            true => serde_json::to_string_pretty(&report),
+            false => serde_json::to_string(&report),
         }
     }
 
-    fn update_up_metric(&mut self) {
-        self.up.set(match self.is_ready() {
+    // Being ready without any conditions is more likely a missing registration than intended, so
+    // this is flagged the first time it is reported
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn warn_if_empty(&self) {
+        let empty = !self
+            .conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .any(|c| !c.optional)
+            && self
+                .children
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty();
+        let policy = *self
+            .empty_policy
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if empty && policy == EmptyPolicy::Ready && !self.warned_empty.swap(true, Ordering::Relaxed)
+        {
+            log::warn!("Reporting ready without any readiness conditions, are any registered?");
+        }
+    }
+
+    pub(crate) fn set_draining(&self, draining: bool) {
+        self.draining.store(draining, Ordering::Relaxed);
+        self.update_metrics();
+    }
+
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn has_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .any(|c| c.state() == ReadinessState::Degraded)
+    }
+
+    pub(crate) fn condition_ages(&self) -> ConditionAges<C> {
+        ConditionAges {
+            conditions: self.conditions.clone(),
+        }
+    }
+
+    fn observe(&self) -> bool {
+        let transitioned: Vec<ConditionLabels> = self
+            .conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter_mut()
+            .filter_map(|(condition, state)| {
+                state.observe().then(|| ConditionLabels::new(condition))
+            })
+            .collect();
+
+        for labels in &transitioned {
+            self.condition_transitions.get_or_create(labels).inc();
+        }
+        !transitioned.is_empty()
+    }
+
+    pub(crate) fn update_metrics(&self) {
+        self.observe();
+
+        let conditions = self
+            .conditions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut ready_count = 0;
+        for (condition, state) in conditions.iter() {
+            let ready = state.is_ready();
+            ready_count += i64::from(ready);
+            self.condition_ready
+                .get_or_create(&ConditionLabels::new(condition))
+                .set(match ready {
+                    true => 1,
+                    false => 0,
+                });
+        }
+        self.conditions_total.set(conditions.len() as i64);
+        self.conditions_ready.set(ready_count);
+        drop(conditions);
+
+        self.score.set(self.score());
+
+        self.degraded.set(match self.is_degraded() {
             true => 1,
             false => 0,
         });
-    }
 
-    fn set_condition_readiness(&mut self, condition: C, ready: bool) {
-        self.conditions.write().unwrap().insert(condition, ready);
+        let ready = self.ready();
+        let up = match ready {
+            true => 1,
+            false => 0,
+        };
+
         if ready {
-            self.update_up_metric();
-        } else {
-            self.up.set(0);
+            self.started.store(true, Ordering::Relaxed);
+        }
+
+        if self.up.set(up) != up {
+            // Cloned so that callbacks are free to use the probe, including registering callbacks
+            let callbacks = self
+                .transition_callbacks
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            for callback in callbacks {
+                callback(ready);
+            }
+        }
+    }
+
+    pub fn on_transition(&self, f: impl Fn(bool) + Send + Sync + 'static) {
+        self.transition_callbacks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::new(f));
+    }
+
+    /// Only considers this probe ready while `child` is also ready, in addition to its own
+    /// conditions. A child must not (indirectly) have this probe as one of its own children.
+    ///
+    /// Changes to the child are reflected in this probe's metrics the next time they are updated
+    /// or its readiness is checked, so do not trigger its transition callbacks straight away.
+    pub fn add_child<D: Hash + Eq + Send + Sync + Serialize + 'static>(
+        &self,
+        child: ReadinessProbe<D>,
+    ) {
+        self.children
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(child));
+        self.update_metrics();
+    }
+
+    /// Sets the weight of `condition` in the probe's score, conditions have a weight of 1.0 by
+    /// default. Fails if `weight` is negative or not finite.
+    pub fn set_condition_weight(&self, condition: C, weight: f64) -> Result<()> {
+        if !weight.is_finite() || weight < 0.0 {
+            bail!(
+                "Condition weight must be finite and not negative, got {}",
+                weight
+            );
+        }
+        self.conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(condition)
+            .or_default()
+            .weight = weight;
+        self.update_metrics();
+        Ok(())
+    }
+
+    /// Considers the probe ready once its score reaches `threshold`, instead of requiring every
+    /// condition to be ready.
+    ///
+    /// This replaces any quorum set by [`ReadinessProbe::set_quorum`].
+    pub fn set_score_threshold(&self, threshold: f64) {
+        if self
+            .quorum
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .is_some()
+        {
+            log::warn!("Setting a readiness score threshold replaces the quorum");
+        }
+        *self
+            .score_threshold
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(threshold);
+        self.update_metrics();
+    }
+
+    pub fn set_empty_policy(&self, policy: EmptyPolicy) {
+        *self
+            .empty_policy
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = policy;
+        self.update_metrics();
+    }
+
+    /// Considers the probe ready once at least `quorum` required conditions are ready, instead of
+    /// requiring every condition to be ready.
+    ///
+    /// This replaces any threshold set by [`ReadinessProbe::set_score_threshold`].
+    pub fn set_quorum(&self, quorum: usize) {
+        if self
+            .score_threshold
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .is_some()
+        {
+            log::warn!("Setting a readiness quorum replaces the score threshold");
+        }
+        *self.quorum.write().unwrap_or_else(PoisonError::into_inner) = Some(quorum);
+        self.update_metrics();
+    }
+
+    /// Keeps a condition not ready for at least `duration` after it was last marked not ready.
+    ///
+    /// The end of a hold is accounted for the next time readiness is checked.
+    pub fn set_min_not_ready_duration(&self, duration: Duration) {
+        *self
+            .min_not_ready
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(duration);
+    }
+
+    fn held_until(&self, state: ReadinessState) -> Option<Instant> {
+        match state {
+            ReadinessState::NotReady => self
+                .min_not_ready
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .map(|duration| Instant::now() + duration),
+            _ => None,
         }
+    }
+
+    pub fn register_condition(&self, condition: C) {
+        self.conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(condition)
+            .or_default();
+        self.update_metrics();
+    }
+
+    pub fn register_with_description(&self, condition: C, description: impl Into<String>) {
+        self.conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(condition)
+            .or_default()
+            .description = Some(description.into());
+        self.update_metrics();
+    }
+
+    pub fn register_optional(&self, condition: C) {
+        self.conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(condition)
+            .or_default()
+            .optional = true;
+        self.update_metrics();
+    }
+
+    fn set_condition_readiness(
+        &self,
+        condition: C,
+        state: ReadinessState,
+        reason: Option<String>,
+        expires: Option<Instant>,
+    ) {
+        let labels = ConditionLabels::new(&condition);
+        let held_until = self.held_until(state);
+
+        let transitions = Self::apply_condition_readiness(
+            &mut self
+                .conditions
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
+            condition,
+            state,
+            reason,
+            expires,
+            held_until,
+        );
+
+        self.condition_transitions
+            .get_or_create(&labels)
+            .inc_by(transitions);
+        self.update_metrics();
         log::trace!("Condition was set");
     }
 
-    pub fn mark_ready(&mut self, condition: C) {
-        self.set_condition_readiness(condition, true);
+    fn apply_condition_readiness(
+        conditions: &mut HashMap<C, ConditionState>,
+        condition: C,
+        state: ReadinessState,
+        reason: Option<String>,
+        expires: Option<Instant>,
+        held_until: Option<Instant>,
+    ) -> u64 {
+        let condition = conditions.entry(condition).or_default();
+        let expired = condition.observe();
+        if condition.state() != state {
+            condition.last_changed = SystemTime::now();
+        }
+        condition.last_updated = Instant::now();
+        condition.state = state;
+        condition.reason = reason;
+        condition.expires = expires;
+        if held_until.is_some() {
+            condition.held_until = held_until;
+        }
+        u64::from(expired) + u64::from(condition.observe())
+    }
+
+    pub fn set_many(&self, updates: impl IntoIterator<Item = (C, bool)>) {
+        let transitioned: Vec<(ConditionLabels, u64)> = {
+            let mut conditions = self
+                .conditions
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            updates
+                .into_iter()
+                .filter_map(|(condition, ready)| {
+                    let labels = ConditionLabels::new(&condition);
+                    let state = match ready {
+                        true => ReadinessState::Ready,
+                        false => ReadinessState::NotReady,
+                    };
+                    let held_until = self.held_until(state);
+                    let transitions = Self::apply_condition_readiness(
+                        &mut conditions,
+                        condition,
+                        state,
+                        None,
+                        None,
+                        held_until,
+                    );
+                    (transitions > 0).then_some((labels, transitions))
+                })
+                .collect()
+        };
+
+        for (labels, transitions) in transitioned {
+            self.condition_transitions
+                .get_or_create(&labels)
+                .inc_by(transitions);
+        }
+        self.update_metrics();
+        log::trace!("Conditions were set");
+    }
+
+    pub fn reset(&self) {
+        self.observe();
+
+        let transitioned: Vec<ConditionLabels> = self
+            .conditions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter_mut()
+            .filter_map(|(condition, state)| {
+                if state.state() != ReadinessState::NotReady {
+                    state.last_changed = SystemTime::now();
+                }
+                state.last_updated = Instant::now();
+                state.state = ReadinessState::NotReady;
+                state.reason = None;
+                state.expires = None;
+                state.observe().then(|| ConditionLabels::new(condition))
+            })
+            .collect();
+
+        for labels in transitioned {
+            self.condition_transitions.get_or_create(&labels).inc();
+        }
+        self.update_metrics();
+        log::trace!("Conditions were reset");
+    }
+
+    pub fn mark_ready(&self, condition: C) {
+        self.set_condition_readiness(condition, ReadinessState::Ready, None, None);
+    }
+
+    pub fn mark_ready_for(&self, condition: C, ttl: Duration) {
+        self.set_condition_readiness(
+            condition,
+            ReadinessState::Ready,
+            None,
+            Some(Instant::now() + ttl),
+        );
+    }
+
+    pub fn mark_degraded(&self, condition: C) {
+        self.set_condition_readiness(condition, ReadinessState::Degraded, None, None);
+    }
+
+    pub fn mark_degraded_with_reason(&self, condition: C, reason: impl Into<String>) {
+        self.set_condition_readiness(
+            condition,
+            ReadinessState::Degraded,
+            Some(reason.into()),
+            None,
+        );
+    }
+
+    pub fn mark_not_ready(&self, condition: C) {
+        self.set_condition_readiness(condition, ReadinessState::NotReady, None, None);
+    }
+
+    pub fn mark_not_ready_with_reason(&self, condition: C, reason: impl Into<String>) {
+        self.set_condition_readiness(
+            condition,
+            ReadinessState::NotReady,
+            Some(reason.into()),
+            None,
+        );
     }
+}
 
-    pub fn mark_not_ready(&mut self, condition: C) {
-        self.set_condition_readiness(condition, false);
+fn weighted_score<'a>(conditions: impl Iterator<Item = &'a ConditionState>) -> f64 {
+    let (ready, total, ready_count, count) = conditions.fold(
+        (0.0, 0.0, 0, 0),
+        |(ready, total, ready_count, count), c| match c.is_ready() {
+            true => (
+                ready + c.weight,
+                total + c.weight,
+                ready_count + 1,
+                count + 1,
+            ),
+            false => (ready, total + c.weight, ready_count, count + 1),
+        },
+    );
+    match (total > 0.0, count > 0) {
+        (true, _) => ready / total,
+        (false, true) => f64::from(ready_count) / f64::from(count),
+        (false, false) => 1.0,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::encode_registry;
 
-    #[derive(Serialize, PartialEq, Eq, Hash, EnumIter)]
+    #[derive(Clone, Serialize, PartialEq, Eq, Hash, EnumIter)]
     enum ReadinessConditions {
         One,
         Two,
         Three,
     }
 
+    impl ConditionDescription for ReadinessConditions {
+        fn description(&self) -> &str {
+            match self {
+                Self::One => "Postgres connection",
+                Self::Two => "Cache warmed",
+                Self::Three => "Upstream API",
+            }
+        }
+    }
+
     #[test]
     fn test_basic() {
-        let mut rc = ReadinessProbe::<ReadinessConditions>::default();
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
         assert!(!rc.is_ready());
         assert_eq!(rc.up.get(), 0);
 
@@ -102,9 +905,747 @@ mod tests {
     }
 
     #[test]
-    fn test_always_ready() {
-        let rc = ReadinessProbe::<AlwaysReady>::default();
+    fn test_condition_set() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash)]
+        struct Upstream(&'static str);
+
+        impl ReadinessConditionSet for Upstream {
+            fn all() -> Vec<Self> {
+                ["a", "b"].into_iter().map(Upstream).collect()
+            }
+        }
+
+        let rc = ReadinessProbe::<Upstream>::default();
+        assert!(!rc.is_ready());
+
+        rc.mark_ready(Upstream("a"));
+        assert!(!rc.is_ready());
+
+        rc.mark_ready(Upstream("b"));
+        assert!(rc.is_ready());
+    }
+
+    #[test]
+    fn test_not_ready_reason() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let conditions = rc.conditions.clone();
+        let json = || serde_json::to_value(&*conditions.read().unwrap()).unwrap();
+
+        assert_eq!(json()["One"]["ready"], false);
+        assert!(json()["One"].get("reason").is_none());
+
+        rc.mark_not_ready_with_reason(ReadinessConditions::One, "connection refused");
+        assert!(!rc.is_ready());
+        assert_eq!(json()["One"]["ready"], false);
+        assert_eq!(json()["One"]["reason"], "connection refused");
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert_eq!(json()["One"]["ready"], true);
+        assert!(json()["One"].get("reason").is_none());
+    }
+
+    #[test]
+    fn test_last_changed() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let last_changed = |rc: &ReadinessProbe<ReadinessConditions>| {
+            rc.conditions.read().unwrap()[&ReadinessConditions::One].last_changed
+        };
+
+        let created = last_changed(&rc);
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert_eq!(last_changed(&rc), created);
+
+        rc.mark_ready(ReadinessConditions::One);
+        let became_ready = last_changed(&rc);
+        assert!(became_ready >= created);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        rc.mark_ready(ReadinessConditions::One);
+        assert_eq!(last_changed(&rc), became_ready);
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert!(last_changed(&rc) > became_ready);
+    }
+
+    #[test]
+    fn test_condition_ages() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let last_updated = |rc: &ReadinessProbe<ReadinessConditions>| {
+            rc.conditions.read().unwrap()[&ReadinessConditions::One].last_updated
+        };
+
+        rc.mark_ready(ReadinessConditions::One);
+        let updated = last_updated(&rc);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        rc.mark_ready(ReadinessConditions::One);
+        assert!(last_updated(&rc) > updated);
+
+        let mut registry = prometheus_client::registry::Registry::default();
+        registry.register("age", "Condition age", rc.condition_ages());
+        let buffer = encode_registry(&registry).unwrap();
+        assert!(buffer.contains("# TYPE age gauge\n"));
+        for condition in ["One", "Two", "Three"] {
+            assert!(buffer.contains(&format!("age{{condition=\"{}\"}} ", condition)));
+        }
+    }
+
+    #[test]
+    fn test_ready_for() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let transitions = Arc::new(RwLock::new(Vec::new()));
+        {
+            let transitions = transitions.clone();
+            rc.on_transition(move |ready| transitions.write().unwrap().push(ready));
+        }
+        let three_transitions = |rc: &ReadinessProbe<ReadinessConditions>| {
+            rc.condition_transitions
+                .get_or_create(&ConditionLabels {
+                    condition: "Three".into(),
+                })
+                .get()
+        };
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready_for(ReadinessConditions::Three, Duration::from_millis(50));
         assert!(rc.is_ready());
         assert_eq!(rc.up.get(), 1);
+        assert_eq!(*transitions.read().unwrap(), vec![true]);
+        assert_eq!(three_transitions(&rc), 1);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false]);
+        assert_eq!(three_transitions(&rc), 2);
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["Three"]["ready"], false);
+        assert_eq!(json["Three"]["reason"], "Readiness expired");
+
+        rc.mark_ready_for(ReadinessConditions::Three, Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(60));
+        rc.mark_ready_for(ReadinessConditions::Three, Duration::from_secs(60));
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false, true]);
+        assert_eq!(three_transitions(&rc), 5);
+    }
+
+    #[test]
+    fn test_on_transition() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+
+        let transitions = Arc::new(RwLock::new(Vec::new()));
+        {
+            let transitions = transitions.clone();
+            rc.on_transition(move |ready| transitions.write().unwrap().push(ready));
+        }
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_not_ready(ReadinessConditions::Two);
+        assert!(transitions.read().unwrap().is_empty());
+
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready(ReadinessConditions::Three);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert_eq!(*transitions.read().unwrap(), vec![true]);
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        rc.mark_not_ready(ReadinessConditions::Two);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_on_transition_reentrant() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+
+        let transitions = Arc::new(RwLock::new(Vec::new()));
+        {
+            let rc_inner = rc.clone();
+            let transitions = transitions.clone();
+            rc.on_transition(move |ready| {
+                assert_eq!(rc_inner.is_ready(), ready);
+                let transitions = transitions.clone();
+                rc_inner.on_transition(move |ready| transitions.write().unwrap().push(ready));
+            });
+        }
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(transitions.read().unwrap().is_empty());
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert_eq!(*transitions.read().unwrap(), vec![false]);
+    }
+
+    #[test]
+    fn test_min_not_ready_duration() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let transitions = Arc::new(RwLock::new(Vec::new()));
+        {
+            let transitions = transitions.clone();
+            rc.on_transition(move |ready| transitions.write().unwrap().push(ready));
+        }
+        let three_transitions = |rc: &ReadinessProbe<ReadinessConditions>| {
+            rc.condition_transitions
+                .get_or_create(&ConditionLabels {
+                    condition: "Three".into(),
+                })
+                .get()
+        };
+
+        rc.set_min_not_ready_duration(Duration::from_millis(50));
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(rc.is_ready());
+
+        rc.mark_not_ready(ReadinessConditions::Three);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false]);
+        assert_eq!(three_transitions(&rc), 2);
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["Three"]["ready"], false);
+        assert_eq!(json["Three"]["reason"], "Recently not ready");
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false, true]);
+        assert_eq!(three_transitions(&rc), 3);
+    }
+
+    #[test]
+    fn test_quorum() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.set_quorum(2);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert!(!rc.is_ready());
+
+        rc.mark_degraded(ReadinessConditions::Three);
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert!(rc.is_ready());
+
+        rc.set_quorum(4);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+
+        rc.set_quorum(0);
+        rc.mark_not_ready(ReadinessConditions::Two);
+        rc.mark_not_ready(ReadinessConditions::Three);
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+    }
+
+    #[test]
+    fn test_score() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.set_condition_weight(ReadinessConditions::One, 2.0)
+            .unwrap();
+        assert_eq!(rc.score(), 0.0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert_eq!(rc.score(), 0.5);
+        assert_eq!(rc.score.get(), 0.5);
+        assert!(!rc.is_ready());
+
+        rc.mark_degraded(ReadinessConditions::Two);
+        assert_eq!(rc.score(), 0.75);
+
+        rc.set_score_threshold(0.75);
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+
+        rc.mark_not_ready(ReadinessConditions::Two);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+
+        let rc = ReadinessProbe::<String>::dynamic();
+        assert_eq!(rc.score(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_weights() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        for weight in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(rc
+                .set_condition_weight(ReadinessConditions::One, weight)
+                .is_err());
+        }
+        assert_eq!(
+            rc.conditions.read().unwrap()[&ReadinessConditions::One].weight,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_zero_weights() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        for condition in ReadinessConditions::all() {
+            rc.set_condition_weight(condition, 0.0).unwrap();
+        }
+        assert_eq!(rc.score(), 0.0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        assert_eq!(rc.score(), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn test_score_threshold_replaces_quorum() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.mark_ready(ReadinessConditions::One);
+
+        rc.set_quorum(1);
+        assert!(rc.is_ready());
+
+        rc.set_score_threshold(0.5);
+        assert!(!rc.is_ready());
+        assert!(rc.quorum.read().unwrap().is_none());
+
+        rc.set_quorum(1);
+        assert!(rc.is_ready());
+        assert!(rc.score_threshold.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_degraded() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_degraded_with_reason(ReadinessConditions::Three, "Running on a replica");
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+        assert_eq!(rc.degraded.get(), 1);
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["Three"]["ready"], true);
+        assert_eq!(json["Three"]["state"], "Degraded");
+        assert_eq!(json["Three"]["reason"], "Running on a replica");
+        assert_eq!(json["One"]["state"], "Ready");
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(rc.degraded.get(), 1);
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert_eq!(rc.up.get(), 1);
+        assert_eq!(rc.degraded.get(), 0);
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let rc = ReadinessProbe::<String>::dynamic();
+        assert!(rc.is_ready());
+
+        rc.register_condition("upstream-a".into());
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+
+        rc.mark_ready("upstream-a".into());
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+
+        rc.register_condition("upstream-a".into());
+        assert!(rc.is_ready());
+
+        rc.mark_not_ready("upstream-b".into());
+        assert!(!rc.is_ready());
+        assert_eq!(rc.conditions.read().unwrap().len(), 2);
+
+        rc.mark_ready("upstream-b".into());
+        assert!(rc.is_ready());
+    }
+
+    #[test]
+    fn test_optional() {
+        let rc = ReadinessProbe::<String>::dynamic();
+        rc.register_condition("database".into());
+        rc.register_optional("cache".into());
+        assert!(!rc.is_ready());
+
+        rc.mark_ready("database".into());
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+
+        rc.mark_not_ready_with_reason("cache".into(), "connection refused");
+        assert!(rc.is_ready());
+        assert_eq!(rc.is_condition_ready(&"cache".into()), Some(false));
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["cache"]["ready"], false);
+        assert_eq!(json["cache"]["optional"], true);
+        assert!(json["database"].get("optional").is_none());
+
+        let gauge = rc
+            .condition_ready
+            .get_or_create(&ConditionLabels {
+                condition: "cache".into(),
+            })
+            .get();
+        assert_eq!(gauge, 0);
+
+        rc.mark_not_ready("database".into());
+        assert!(!rc.is_ready());
+    }
+
+    #[test]
+    fn test_descriptions() {
+        let rc = ReadinessProbe::<ReadinessConditions>::with_descriptions();
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["One"]["description"], "Postgres connection");
+        assert_eq!(json["Three"]["description"], "Upstream API");
+        assert_eq!(json["Two"]["ready"], false);
+
+        let rc = ReadinessProbe::<String>::dynamic();
+        rc.register_with_description("database".into(), "Postgres connection");
+        rc.mark_ready("upstream".into());
+        rc.register_with_description("upstream".into(), "Upstream API");
+        assert_eq!(rc.is_condition_ready(&"upstream".into()), Some(true));
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert_eq!(json["database"]["description"], "Postgres connection");
+        assert_eq!(json["upstream"]["description"], "Upstream API");
+    }
+
+    #[test]
+    fn test_empty_policy() {
+        let rc = ReadinessProbe::<String>::dynamic();
+        assert!(rc.is_ready());
+
+        rc.set_empty_policy(EmptyPolicy::NotReady);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+
+        rc.register_optional("cache".into());
+        rc.mark_ready("cache".into());
+        assert!(!rc.is_ready());
+
+        rc.mark_ready("database".into());
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+    }
+
+    #[test]
+    fn test_draining() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.set_many([
+            (ReadinessConditions::One, true),
+            (ReadinessConditions::Two, true),
+            (ReadinessConditions::Three, true),
+        ]);
+        assert!(rc.is_ready());
+
+        rc.set_draining(true);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert!(rc
+            .report_json(false, false)
+            .unwrap()
+            .starts_with("{\"ready\":false,\"draining\":true,\"not_ready\":[],"));
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert!(!rc.is_ready());
+
+        rc.set_draining(false);
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+    }
+
+    #[test]
+    fn test_warn_if_empty() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.warn_if_empty();
+        assert!(!rc.warned_empty.load(Ordering::Relaxed));
+
+        let rc = ReadinessProbe::<String>::dynamic();
+        rc.set_empty_policy(EmptyPolicy::NotReady);
+        rc.warn_if_empty();
+        assert!(!rc.warned_empty.load(Ordering::Relaxed));
+
+        rc.set_empty_policy(EmptyPolicy::Ready);
+        rc.warn_if_empty();
+        assert!(rc.warned_empty.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.register_optional(ReadinessConditions::Three);
+        rc.mark_ready(ReadinessConditions::Two);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&rc.report_json(rc.is_ready(), false).unwrap()).unwrap();
+        assert_eq!(report["ready"], false);
+        assert_eq!(report["not_ready"], serde_json::json!(["One"]));
+        assert_eq!(report["conditions"]["Two"]["state"], "Ready");
+        assert_eq!(report["conditions"]["Three"]["optional"], true);
+
+        let pretty = rc.report_json(false, true).unwrap();
+        assert!(pretty.starts_with("{\n  \"ready\": false,\n"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    fn test_condition_counts() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        assert_eq!(rc.conditions_total.get(), 3);
+        assert_eq!(rc.conditions_ready.get(), 0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_degraded(ReadinessConditions::Two);
+        assert_eq!(rc.conditions_ready.get(), 2);
+
+        rc.reset();
+        assert_eq!(rc.conditions_total.get(), 3);
+        assert_eq!(rc.conditions_ready.get(), 0);
+
+        let rc = ReadinessProbe::<String>::dynamic();
+        assert_eq!(rc.conditions_total.get(), 0);
+        rc.register_condition("database".into());
+        assert_eq!(rc.conditions_total.get(), 1);
+    }
+
+    #[test]
+    fn test_add_child() {
+        let parent = ReadinessProbe::<String>::dynamic();
+        let child = ReadinessProbe::<ReadinessConditions>::default();
+        let other_child = ReadinessProbe::<AlwaysReady>::default();
+
+        parent.add_child(other_child);
+        parent.add_child(child.clone());
+        assert!(!parent.is_ready());
+        assert_eq!(parent.up.get(), 0);
+
+        child.set_many([
+            (ReadinessConditions::One, true),
+            (ReadinessConditions::Two, true),
+            (ReadinessConditions::Three, true),
+        ]);
+        assert!(parent.is_ready());
+
+        parent.mark_not_ready("database".into());
+        assert!(!parent.is_ready());
+        parent.mark_ready("database".into());
+        assert_eq!(parent.up.get(), 1);
+        assert_eq!(parent.conditions.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_condition_gauges() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let gauge = |rc: &ReadinessProbe<ReadinessConditions>, name: &str| {
+            rc.condition_ready
+                .get_or_create(&ConditionLabels {
+                    condition: name.into(),
+                })
+                .get()
+        };
+
+        assert_eq!(gauge(&rc, "One"), 0);
+        assert_eq!(gauge(&rc, "Two"), 0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert_eq!(gauge(&rc, "One"), 1);
+        assert_eq!(gauge(&rc, "Two"), 0);
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert_eq!(gauge(&rc, "One"), 0);
+    }
+
+    #[test]
+    fn test_condition_transitions() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        let transitions = |rc: &ReadinessProbe<ReadinessConditions>, name: &str| {
+            rc.condition_transitions
+                .get_or_create(&ConditionLabels {
+                    condition: name.into(),
+                })
+                .get()
+        };
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        assert_eq!(transitions(&rc, "One"), 0);
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::One);
+        assert_eq!(transitions(&rc, "One"), 1);
+
+        rc.mark_degraded(ReadinessConditions::One);
+        assert_eq!(transitions(&rc, "One"), 1);
+
+        rc.mark_not_ready(ReadinessConditions::One);
+        rc.mark_not_ready_with_reason(ReadinessConditions::One, "Still broken");
+        assert_eq!(transitions(&rc, "One"), 2);
+        assert_eq!(transitions(&rc, "Two"), 0);
+    }
+
+    #[test]
+    fn test_started() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        assert!(!rc.has_started());
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        assert!(!rc.has_started());
+
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(rc.has_started());
+
+        rc.mark_not_ready(ReadinessConditions::Two);
+        assert!(!rc.is_ready());
+        assert!(rc.has_started());
+    }
+
+    #[test]
+    fn test_always_ready() {
+        let rc = ReadinessProbe::<AlwaysReady>::default();
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+    }
+
+    #[test]
+    fn test_conditions_snapshot() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.mark_ready(ReadinessConditions::Two);
+
+        let snapshot = rc.conditions_snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert!(!snapshot[&ReadinessConditions::One]);
+        assert!(snapshot[&ReadinessConditions::Two]);
+        assert!(!snapshot[&ReadinessConditions::Three]);
+
+        rc.mark_ready(ReadinessConditions::One);
+        assert!(!snapshot[&ReadinessConditions::One]);
+    }
+
+    #[test]
+    fn test_is_condition_ready() {
+        let rc = ReadinessProbe::<String>::dynamic();
+        assert_eq!(rc.is_condition_ready(&"upstream-a".into()), None);
+
+        rc.register_condition("upstream-a".into());
+        assert_eq!(rc.is_condition_ready(&"upstream-a".into()), Some(false));
+
+        rc.mark_degraded("upstream-a".into());
+        assert_eq!(rc.is_condition_ready(&"upstream-a".into()), Some(true));
+
+        rc.mark_not_ready("upstream-a".into());
+        assert_eq!(rc.is_condition_ready(&"upstream-a".into()), Some(false));
+        assert_eq!(rc.is_condition_ready(&"upstream-b".into()), None);
+    }
+
+    #[test]
+    fn test_set_many() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+
+        let transitions = Arc::new(RwLock::new(Vec::new()));
+        {
+            let transitions = transitions.clone();
+            rc.on_transition(move |ready| transitions.write().unwrap().push(ready));
+        }
+
+        rc.set_many([
+            (ReadinessConditions::One, true),
+            (ReadinessConditions::Two, true),
+            (ReadinessConditions::Three, true),
+        ]);
+        assert!(rc.is_ready());
+        assert_eq!(rc.up.get(), 1);
+        assert_eq!(*transitions.read().unwrap(), vec![true]);
+
+        rc.set_many([
+            (ReadinessConditions::One, false),
+            (ReadinessConditions::Two, true),
+        ]);
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(*transitions.read().unwrap(), vec![true, false]);
+
+        let one = rc
+            .condition_transitions
+            .get_or_create(&ConditionLabels::new(&ReadinessConditions::One))
+            .get();
+        assert_eq!(one, 2);
+        let two = rc
+            .condition_transitions
+            .get_or_create(&ConditionLabels::new(&ReadinessConditions::Two))
+            .get();
+        assert_eq!(two, 1);
+    }
+
+    #[test]
+    fn test_reset() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_degraded_with_reason(ReadinessConditions::Three, "Running on a replica");
+        assert!(rc.is_ready());
+        assert_eq!(rc.degraded.get(), 1);
+
+        rc.reset();
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(rc.degraded.get(), 0);
+        assert!(rc.conditions_snapshot().values().all(|ready| !ready));
+
+        let json = serde_json::to_value(&*rc.conditions.read().unwrap()).unwrap();
+        assert!(json["Three"].get("reason").is_none());
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(rc.is_ready());
+    }
+
+    #[test]
+    fn test_reset_dynamic() {
+        let rc = ReadinessProbe::<String>::dynamic();
+        rc.mark_ready("upstream-a".into());
+        assert!(rc.is_ready());
+
+        rc.reset();
+        assert!(!rc.is_ready());
+        assert_eq!(rc.up.get(), 0);
+        assert_eq!(rc.conditions.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_poisoned_lock() {
+        let rc = ReadinessProbe::<ReadinessConditions>::default();
+
+        let conditions = rc.conditions.clone();
+        std::thread::spawn(move || {
+            let _guard = conditions.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(rc.conditions.is_poisoned());
+
+        rc.mark_ready(ReadinessConditions::One);
+        rc.mark_ready(ReadinessConditions::Two);
+        rc.mark_ready(ReadinessConditions::Three);
+        assert!(rc.is_ready());
     }
 }
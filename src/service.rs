@@ -0,0 +1,2120 @@
+use super::{
+    connection::ConnectionInfo,
+    encoding::{encode_registry, encode_registry_into},
+    limit::{RequestLimits, ServerLimits},
+    metrics_json,
+    readiness_probe::ReadinessProbe,
+    watchdog::Watchdog,
+};
+use anyhow::{bail, Result};
+#[cfg(feature = "axum")]
+use axum::{extract::State, routing::any};
+use flate2::{write::GzEncoder, Compression};
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{
+        HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW, AUTHORIZATION,
+        CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, ORIGIN, RETRY_AFTER,
+        VARY, WWW_AUTHENTICATE,
+    },
+    Body, Method, Request, Response, StatusCode,
+};
+use ipnet::IpNet;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::{
+    counter::Counter,
+    family::Family,
+    gauge::Gauge,
+    histogram::{exponential_buckets, Histogram},
+};
+use prometheus_client::registry::Registry;
+use serde::Serialize;
+use std::{
+    cmp::Eq,
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::Infallible,
+    future::Future,
+    hash::{Hash, Hasher},
+    io::Write,
+    marker::{Send, Sync},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{atomic::AtomicU64, Arc, Mutex, PoisonError, RwLock, TryLockError},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tower::{util::BoxCloneService, ServiceExt};
+
+pub(crate) const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone)]
+pub(crate) struct Paths {
+    pub(crate) metrics: String,
+    pub(crate) ready: String,
+    pub(crate) alive: String,
+    pub(crate) startup: String,
+    pub(crate) metrics_json: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) health: Option<String>,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self {
+            metrics: "/metrics".into(),
+            ready: "/ready".into(),
+            alive: "/alive".into(),
+            startup: "/startup".into(),
+            metrics_json: None,
+            version: None,
+            health: None,
+        }
+    }
+}
+
+impl Paths {
+    pub(crate) fn all(&self) -> impl Iterator<Item = &String> {
+        [&self.metrics, &self.ready, &self.alive, &self.startup]
+            .into_iter()
+            .chain(&self.metrics_json)
+            .chain(&self.version)
+            .chain(&self.health)
+    }
+
+    fn is_metrics(&self, path: &str) -> bool {
+        path == self.metrics || self.metrics_json.as_deref() == Some(path)
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        let paths: Vec<&String> = self.all().collect();
+
+        for (i, path) in paths.iter().enumerate() {
+            if !path.starts_with('/') {
+                bail!("Path \"{}\" does not start with \"/\"", path);
+            }
+            if paths[..i].contains(path) {
+                bail!("Path \"{}\" is used for more than one endpoint", path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) type RouteHandler = Arc<dyn Fn(Request<Body>) -> Response<Body> + Send + Sync>;
+pub(crate) type LivenessCheck = Arc<dyn Fn() -> bool + Send + Sync>;
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub(crate) type ServiceLayer = Arc<dyn Fn(BoxedService) -> BoxedService + Send + Sync>;
+
+/// The type erased service that layers added with [`Watcher::with_layer`](crate::Watcher::with_layer)
+/// wrap.
+pub type BoxedService = BoxCloneService<Request<Body>, Response<Body>, BoxError>;
+
+#[derive(Clone)]
+pub(crate) struct LivenessResponse {
+    pub(crate) status: StatusCode,
+    pub(crate) body: Bytes,
+    pub(crate) content_type: String,
+}
+
+impl Default for LivenessResponse {
+    fn default() -> Self {
+        Self {
+            status: StatusCode::OK,
+            body: Bytes::from_static(b"alive"),
+            content_type: "text/plain".into(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Config {
+    pub(crate) paths: Paths,
+    pub(crate) routes: HashMap<String, RouteHandler>,
+    pub(crate) disable_index: bool,
+    pub(crate) shutdown_timeout: Option<Duration>,
+    pub(crate) request_timeouts: HashMap<String, Duration>,
+    pub(crate) termination_signal_capacity: Option<usize>,
+    pub(crate) ready_retry_after: Option<Duration>,
+    pub(crate) metrics_allowlist: Option<Vec<IpNet>>,
+    pub(crate) cors_allowed_origins: Vec<String>,
+    pub(crate) version_info: Vec<(String, String)>,
+    pub(crate) liveness_response: LivenessResponse,
+    pub(crate) liveness_checks: Vec<LivenessCheck>,
+    pub(crate) watchdog: Watchdog,
+    pub(crate) watchdog_timeout: Option<Duration>,
+    pub(crate) server_limits: ServerLimits,
+    pub(crate) request_limits: RequestLimits,
+    pub(crate) access_log: bool,
+    pub(crate) ready_pretty: bool,
+    pub(crate) ready_content_type: Option<String>,
+    pub(crate) reuse_address: bool,
+    pub(crate) reuse_port: bool,
+    #[cfg(feature = "http2")]
+    pub(crate) http2: bool,
+    pub(crate) runtime: Option<tokio::runtime::Handle>,
+    pub(crate) layer: Option<ServiceLayer>,
+    pub(crate) bearer_token: Option<String>,
+    pub(crate) metrics_cache_ttl: Duration,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    path: String,
+    status: u16,
+}
+
+#[derive(Clone)]
+pub(crate) struct HttpMetrics {
+    requests: Family<RequestLabels, Counter>,
+    request_duration: Histogram,
+    requests_in_flight: Gauge,
+    scrape_duration: Histogram,
+    last_scrape: Gauge<f64, AtomicU64>,
+}
+
+impl Default for HttpMetrics {
+    fn default() -> Self {
+        Self {
+            requests: Family::default(),
+            request_duration: Histogram::new(exponential_buckets(0.0001, 4.0, 8)),
+            requests_in_flight: Gauge::default(),
+            scrape_duration: Histogram::new(exponential_buckets(0.0001, 4.0, 8)),
+            last_scrape: Gauge::default(),
+        }
+    }
+}
+
+impl HttpMetrics {
+    pub(crate) fn register(&self, registry: &mut Registry) {
+        registry.register(
+            "watcher_http_requests",
+            "Number of requests handled by the watcher server",
+            self.requests.clone(),
+        );
+        registry.register(
+            "watcher_http_request_duration_seconds",
+            "Time taken to handle requests to the watcher server",
+            self.request_duration.clone(),
+        );
+        registry.register(
+            "watcher_http_requests_in_flight",
+            "Number of requests currently being handled by the watcher server",
+            self.requests_in_flight.clone(),
+        );
+        registry.register(
+            "watcher_scrape_duration_seconds",
+            "Time taken to encode the metrics registry",
+            self.scrape_duration.clone(),
+        );
+        registry.register(
+            "watcher_last_scrape_timestamp_seconds",
+            "Time of the last metrics scrape since unix epoch in seconds",
+            self.last_scrape.clone(),
+        );
+    }
+}
+
+struct InFlight(Gauge);
+
+impl InFlight {
+    fn new(gauge: Gauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[derive(Clone)]
+pub struct WatcherService<C: Hash + Eq + Send + Sync + Serialize> {
+    metrics_registry: Arc<RwLock<Registry>>,
+    readiness_probe: ReadinessProbe<C>,
+    config: Arc<Config>,
+    http_metrics: HttpMetrics,
+    connection: ConnectionInfo,
+    metrics_cache: Arc<Mutex<Option<(Instant, Bytes)>>>,
+    encode_buffer: Arc<Mutex<String>>,
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> WatcherService<C> {
+    pub(crate) fn new(
+        metrics_registry: Arc<RwLock<Registry>>,
+        readiness_probe: ReadinessProbe<C>,
+        config: Config,
+        http_metrics: HttpMetrics,
+    ) -> Self {
+        Self {
+            metrics_registry,
+            readiness_probe,
+            config: Arc::new(config),
+            http_metrics,
+            connection: ConnectionInfo::default(),
+            metrics_cache: Arc::new(Mutex::new(None)),
+            encode_buffer: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    fn encode_metrics(&self) -> Result<Bytes> {
+        let ttl = self.config.metrics_cache_ttl;
+        if ttl.is_zero() {
+            return self.encode_registry();
+        }
+
+        // Hold the cache lock while encoding so that concurrent scrapes share one encode
+        let mut cache = self
+            .metrics_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some((encoded_at, buffer)) = cache.as_ref() {
+            if encoded_at.elapsed() < ttl {
+                return Ok(buffer.clone());
+            }
+        }
+
+        let buffer = self.encode_registry()?;
+        *cache = Some((Instant::now(), buffer.clone()));
+        Ok(buffer)
+    }
+
+    fn encode_registry(&self) -> Result<Bytes> {
+        let start = Instant::now();
+        let buffer = self.encode_registry_inner();
+        self.http_metrics
+            .scrape_duration
+            .observe(start.elapsed().as_secs_f64());
+        buffer
+    }
+
+    fn encode_registry_inner(&self) -> Result<Bytes> {
+        self.readiness_probe.update_metrics();
+        let registry = self
+            .metrics_registry
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // Reuse the buffer from previous scrapes so it does not need to grow again, falling
+        // back to a fresh allocation if another request is currently encoding
+        let buffer = match self.encode_buffer.try_lock() {
+            Ok(buffer) => Some(buffer),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        };
+
+        match buffer {
+            Some(mut buffer) => {
+                buffer.clear();
+                encode_registry_into(&mut buffer, &registry)?;
+                Ok(Bytes::copy_from_slice(buffer.as_bytes()))
+            }
+            None => Ok(encode_registry(&registry)?.into()),
+        }
+    }
+
+    pub(crate) fn with_connection(mut self, connection: ConnectionInfo) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    pub(crate) fn into_layered(self) -> BoxedService {
+        let layer = self.config.layer.clone();
+        let service = BoxedService::new(self.map_err(BoxError::from));
+        match layer {
+            Some(layer) => layer(service),
+            None => service,
+        }
+    }
+
+    async fn serve_request(self, req: Request<Body>) -> Response<Body> {
+        let req = match self.limit_body(req).await {
+            Ok(req) => req,
+            Err(e) => {
+                let mut response = Response::new(Body::from(format!("Failed to read body: {e}")));
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+                return response;
+            }
+        };
+
+        let Some(&timeout) = self.config.request_timeouts.get(req.uri().path()) else {
+            return self.handle_request(req);
+        };
+
+        // Handlers are synchronous, so are run on the blocking pool where they can be abandoned
+        // once the timeout expires, though they are left to run to completion
+        let path = req.uri().path().to_owned();
+        let handle = tokio::task::spawn_blocking(move || self.handle_request(req));
+        match tokio::time::timeout(timeout, handle).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => internal_server_error(e.into()),
+            Err(_) => {
+                event!(warn, { %path, ?timeout, }, "Request for {} timed out after {:?}", path, timeout);
+                let mut response = Response::new(Body::from("Request timed out"));
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+                response
+            }
+        }
+    }
+
+    fn handle_request(&self, mut req: Request<Body>) -> Response<Body> {
+        let start = Instant::now();
+        let _in_flight = InFlight::new(self.http_metrics.requests_in_flight.clone());
+        let path = self.route(req.uri().path()).to_owned();
+        // Captured up front as custom handlers take ownership of the request
+        let access_log = self
+            .config
+            .access_log
+            .then(|| (req.method().clone(), req.uri().path().to_owned()));
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "request",
+            method = %req.method(),
+            path = req.uri().path(),
+            remote_addr = tracing::field::debug(self.connection.remote_addr),
+            status = tracing::field::Empty,
+            duration = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        #[cfg(not(feature = "tracing"))]
+        log::debug!(
+            "{} {} from {:?}",
+            req.method(),
+            req.uri().path(),
+            self.connection.remote_addr
+        );
+
+        // Built in routes take precedence over custom ones
+        let handler = match self.is_route(req.uri().path()) {
+            true => None,
+            false => self.config.routes.get(req.uri().path()),
+        };
+
+        let response = match handler {
+            _ if self.exceeds_header_limit(&req) => {
+                let mut response = Response::new(Body::from("Too many headers"));
+                *response.status_mut() = StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE;
+                response
+            }
+            _ if self.exceeds_body_limit(&req) => {
+                let mut response = Response::new(Body::from("Payload too large"));
+                *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                response
+            }
+            Some(handler) => {
+                self.connection.insert_extensions(&mut req);
+                handler(req)
+            }
+            None => {
+                let mut response = self.respond(&req).unwrap_or_else(internal_server_error);
+                self.add_cors_headers(&req, &mut response);
+                response
+            }
+        };
+
+        self.http_metrics
+            .requests
+            .get_or_create(&RequestLabels {
+                path,
+                status: response.status().as_u16(),
+            })
+            .inc();
+        let duration = start.elapsed();
+        self.http_metrics
+            .request_duration
+            .observe(duration.as_secs_f64());
+
+        #[cfg(feature = "tracing")]
+        {
+            span.record("status", response.status().as_u16());
+            span.record("duration", tracing::field::debug(duration));
+        }
+
+        if let Some((method, path)) = access_log {
+            let status = response.status().as_u16();
+            let size = response.body().size_hint().exact();
+            let remote_addr = self.connection.remote_addr;
+            event!(
+                info,
+                { %method, %path, status, ?size, ?duration, ?remote_addr, },
+                "{}",
+                access_log_message(&method, &path, status, size, duration, remote_addr)
+            );
+        }
+
+        response
+    }
+
+    // Bodies without a declared length (such as chunked bodies) are only known to be too large
+    // once streamed, so are read up front, stopping once the limit is crossed
+    async fn limit_body(&self, req: Request<Body>) -> Result<Request<Body>, hyper::Error> {
+        let Some(max) = self.config.request_limits.max_body_size else {
+            return Ok(req);
+        };
+        if req.headers().contains_key(CONTENT_LENGTH) {
+            return Ok(req);
+        }
+
+        let (mut parts, mut body) = req.into_parts();
+        let mut buffer = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            if (buffer.len() + chunk.len()) as u64 > max {
+                parts.extensions.insert(BodyTooLarge);
+                return Ok(Request::from_parts(parts, Body::empty()));
+            }
+            buffer.extend_from_slice(&chunk);
+        }
+        Ok(Request::from_parts(parts, Body::from(buffer)))
+    }
+
+    fn exceeds_header_limit(&self, req: &Request<Body>) -> bool {
+        self.config
+            .request_limits
+            .max_headers
+            .is_some_and(|max| req.headers().len() > max)
+    }
+
+    fn exceeds_body_limit(&self, req: &Request<Body>) -> bool {
+        let Some(max) = self.config.request_limits.max_body_size else {
+            return false;
+        };
+        req.extensions().get::<BodyTooLarge>().is_some()
+            || req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse::<u64>().ok())
+                .is_some_and(|length| length > max)
+    }
+
+    fn route<'a>(&self, path: &'a str) -> &'a str {
+        match self.is_route(path) || self.config.routes.contains_key(path) {
+            true => path,
+            false => "other",
+        }
+    }
+
+    fn is_route(&self, path: &str) -> bool {
+        self.config.paths.all().any(|route| route == path)
+    }
+
+    // Built in routes and the index are only served for GET and HEAD
+    fn is_read_only(&self, path: &str) -> bool {
+        self.is_route(path) || (path == "/" && !self.config.disable_index)
+    }
+
+    fn add_cors_headers(&self, req: &Request<Body>, response: &mut Response<Body>) {
+        let allowed_origins = &self.config.cors_allowed_origins;
+        if allowed_origins.is_empty() {
+            return;
+        }
+
+        let headers = response.headers_mut();
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+
+        if let Some(origin) = req.headers().get(ORIGIN) {
+            if allowed_origins
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == origin)
+            {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            }
+        }
+    }
+
+    fn is_allowed_metrics_peer(&self) -> bool {
+        match &self.config.metrics_allowlist {
+            Some(allowlist) => self.connection.remote_addr.is_some_and(|remote_addr| {
+                let ip = remote_addr.ip().to_canonical();
+                allowlist.iter().any(|net| net.contains(&ip))
+            }),
+            None => true,
+        }
+    }
+
+    fn index(&self) -> String {
+        let mut custom: Vec<&String> = self.config.routes.keys().collect();
+        custom.sort();
+
+        let links: String = self
+            .config
+            .paths
+            .all()
+            .chain(custom)
+            .map(|path| {
+                let path = escape_html(path);
+                format!("<li><a href=\"{path}\">{path}</a></li>")
+            })
+            .collect();
+
+        format!("<!DOCTYPE html><html><body><ul>{links}</ul></body></html>")
+    }
+
+    fn is_alive(&self) -> bool {
+        let stalled = self
+            .config
+            .watchdog_timeout
+            .is_some_and(|timeout| self.config.watchdog.since_last_pet() > timeout);
+        !stalled && self.config.liveness_checks.iter().all(|check| check())
+    }
+
+    fn respond(&self, req: &Request<Body>) -> Result<Response<Body>> {
+        let paths = &self.config.paths;
+
+        Ok(match req.uri().path() {
+            path if self.is_read_only(path)
+                && req.method() == Method::OPTIONS
+                && !self.config.cors_allowed_origins.is_empty() =>
+            {
+                Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .header(ALLOW, "GET, HEAD, OPTIONS")
+                    .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD")
+                    .header(ACCESS_CONTROL_ALLOW_HEADERS, "Authorization")
+                    .body(Body::empty())?
+            }
+            path if self.is_read_only(path)
+                && !matches!(*req.method(), Method::GET | Method::HEAD) =>
+            {
+                Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(ALLOW, "GET, HEAD")
+                    .body("Method not allowed".into())?
+            }
+            path if paths.is_metrics(path) && !self.is_allowed_metrics_peer() => {
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body("Forbidden".into())?
+            }
+            path if paths.is_metrics(path)
+                && !is_authorized(req, self.config.bearer_token.as_deref()) =>
+            {
+                Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(WWW_AUTHENTICATE, "Bearer")
+                    .body("Unauthorized".into())?
+            }
+            path if path == paths.metrics && MetricsFormat::negotiate(req).is_none() => {
+                let formats: Vec<&str> = MetricsFormat::ALL
+                    .iter()
+                    .map(|format| format.content_type())
+                    .collect();
+                Response::builder()
+                    .status(StatusCode::NOT_ACCEPTABLE)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(format!("Supported formats: {}", formats.join(", ")).into())?
+            }
+            path if path == paths.metrics => {
+                let format = MetricsFormat::negotiate(req).unwrap_or_default();
+                self.http_metrics.last_scrape.set(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64(),
+                );
+                let mut buffer = self.encode_metrics()?;
+
+                if format != MetricsFormat::OpenMetrics {
+                    buffer = to_prometheus_text(buffer);
+                }
+                if format == MetricsFormat::Json {
+                    buffer = metrics_json::encode(std::str::from_utf8(&buffer)?)?.into();
+                }
+
+                // The watcher's own metrics change on every scrape, so the encoding only repeats
+                // while it is cached
+                let etag = (!self.config.metrics_cache_ttl.is_zero()).then(|| etag(&buffer));
+                let mut response = Response::builder()
+                    .header(CONTENT_TYPE, format.content_type())
+                    .header(VARY, "Accept, Accept-Encoding");
+                if let Some(etag) = &etag {
+                    response = response.header(ETAG, etag);
+                }
+
+                if etag.as_deref().is_some_and(|etag| matches_etag(req, etag)) {
+                    response
+                        .status(StatusCode::NOT_MODIFIED)
+                        .body(Body::empty())?
+                } else if accepts(req, ACCEPT_ENCODING, "gzip") {
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&buffer)?;
+                    response
+                        .status(StatusCode::OK)
+                        .header(CONTENT_ENCODING, "gzip")
+                        .body(Body::from(encoder.finish()?))?
+                } else {
+                    response.status(StatusCode::OK).body(Body::from(buffer))?
+                }
+            }
+            path if paths.metrics_json.as_deref() == Some(path) => {
+                let buffer = self.encode_metrics()?;
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(metrics_json::encode(std::str::from_utf8(&buffer)?)?.into())?
+            }
+            path if paths.version.as_deref() == Some(path) => {
+                let info: serde_json::Map<String, serde_json::Value> = self
+                    .config
+                    .version_info
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone().into()))
+                    .collect();
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_string(&info)?.into())?
+            }
+            path if path == paths.ready => {
+                self.readiness_probe.warn_if_empty();
+                let ready = self.readiness_probe.is_ready();
+                let mut response = Response::builder()
+                    .status(match ready {
+                        true => StatusCode::OK,
+                        false => StatusCode::SERVICE_UNAVAILABLE,
+                    })
+                    .header(
+                        CONTENT_TYPE,
+                        self.config
+                            .ready_content_type
+                            .as_deref()
+                            .unwrap_or("application/json"),
+                    );
+                if let (false, Some(retry_after)) = (ready, self.config.ready_retry_after) {
+                    // Rounded up so that a sub-second hint is not sent as zero
+                    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    response = response.header(RETRY_AFTER, seconds);
+                }
+                let body = self
+                    .readiness_probe
+                    .report_json(ready, self.config.ready_pretty)
+                    .unwrap_or_else(|e| {
+                        log::warn!("Failed to serialize readiness conditions: {}", e);
+                        format!("{{\"ready\":{ready}}}")
+                    });
+                response.body(Body::from(body))?
+            }
+            path if path == paths.startup => {
+                let started = self.readiness_probe.has_started();
+                Response::builder()
+                    .status(match started {
+                        true => StatusCode::OK,
+                        false => StatusCode::SERVICE_UNAVAILABLE,
+                    })
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(match started {
+                        true => "started".into(),
+                        false => "starting".into(),
+                    })?
+            }
+            path if path == paths.alive => match self.is_alive() {
+                true => {
+                    let alive = &self.config.liveness_response;
+                    Response::builder()
+                        .status(alive.status)
+                        .header(CONTENT_TYPE, &alive.content_type)
+                        .body(Body::from(alive.body.clone()))?
+                }
+                false => Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body("not alive".into())?,
+            },
+            path if paths.health.as_deref() == Some(path) => {
+                let alive = self.is_alive();
+                let ready = self.readiness_probe.is_ready();
+                Response::builder()
+                    .status(match alive && ready {
+                        true => StatusCode::OK,
+                        false => StatusCode::SERVICE_UNAVAILABLE,
+                    })
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(format!("{{\"alive\":{alive},\"ready\":{ready}}}").into())?
+            }
+            "/" if !self.config.disable_index => Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(self.index().into())?,
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(CONTENT_TYPE, "text/plain")
+                .body("Not found".into())?,
+        })
+    }
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> tower::Service<Request<Body>>
+    for WatcherService<C>
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move { Ok(service.serve_request(req).await) })
+    }
+}
+
+#[cfg(feature = "axum")]
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> WatcherService<C> {
+    pub(crate) fn into_router(self) -> axum::Router {
+        let paths = self.config.paths.clone();
+
+        let mut router = axum::Router::new();
+        for path in paths.all() {
+            router = router.route(path, any(handle::<C>));
+        }
+
+        for path in self.config.routes.keys() {
+            if !self.is_route(path) {
+                router = router.route(path, any(handle::<C>));
+            }
+        }
+
+        if !self.config.disable_index
+            && !self.is_route("/")
+            && !self.config.routes.contains_key("/")
+        {
+            router = router.route("/", any(handle::<C>));
+        }
+
+        router.with_state(self)
+    }
+}
+
+#[cfg(feature = "axum")]
+async fn handle<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize>(
+    State(service): State<WatcherService<C>>,
+    req: Request<Body>,
+) -> Response<Body> {
+    service.serve_request(req).await
+}
+
+fn access_log_message(
+    method: &Method,
+    path: &str,
+    status: u16,
+    size: Option<u64>,
+    duration: Duration,
+    remote_addr: Option<SocketAddr>,
+) -> String {
+    let size = size.map_or_else(|| "-".into(), |size| size.to_string());
+    let remote_addr = remote_addr.map_or_else(|| "-".into(), |addr| addr.to_string());
+    format!("{remote_addr} \"{method} {path}\" {status} {size} {duration:?}")
+}
+
+// Marks requests whose streamed body crossed the size limit
+#[derive(Clone, Copy)]
+struct BodyTooLarge;
+
+fn internal_server_error(e: anyhow::Error) -> Response<Body> {
+    event!(error, { error = %e, }, "Failed to handle request: {}", e);
+    let mut response = Response::new(Body::from("Internal server error"));
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    response
+}
+
+fn is_authorized(req: &Request<Body>, token: Option<&str>) -> bool {
+    match token {
+        Some(token) => req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            // Authentication schemes are case insensitive (RFC 7235)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+            .is_some_and(|(_, value)| constant_time_eq(value.as_bytes(), token.as_bytes())),
+        None => true,
+    }
+}
+
+fn accepts(req: &Request<Body>, header: HeaderName, value: &str) -> bool {
+    req.headers()
+        .get_all(header)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let disabled = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            name.eq_ignore_ascii_case(value) && !disabled
+        })
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum MetricsFormat {
+    #[default]
+    Prometheus,
+    OpenMetrics,
+    Json,
+}
+
+impl MetricsFormat {
+    // In order of preference between formats a client accepts equally
+    const ALL: [Self; 3] = [Self::Prometheus, Self::OpenMetrics, Self::Json];
+
+    fn media_type(self) -> &'static str {
+        match self {
+            Self::Prometheus => "text/plain",
+            Self::OpenMetrics => "application/openmetrics-text",
+            Self::Json => "application/json",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            Self::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+            Self::Json => "application/json",
+        }
+    }
+
+    // Picks the format with the highest quality in the Accept header, taken from the most
+    // specific media range matching each format. Only clients that accept none of the formats,
+    // such as scrapers insisting on protobuf, are turned away.
+    fn negotiate(req: &Request<Body>) -> Option<Self> {
+        let ranges: Vec<(&str, f32)> = req
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                let name = params.next().filter(|name| !name.is_empty())?;
+                let q = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((name, q))
+            })
+            .collect();
+        if ranges.is_empty() {
+            return Some(Self::default());
+        }
+
+        Self::ALL
+            .into_iter()
+            .filter_map(|format| {
+                let media_type = format.media_type();
+                let (kind, _) = media_type.split_once('/')?;
+                ranges
+                    .iter()
+                    .filter_map(|&(range, q)| {
+                        let specificity = if range.eq_ignore_ascii_case(media_type) {
+                            2
+                        } else if range
+                            .strip_suffix("/*")
+                            .is_some_and(|range| range.eq_ignore_ascii_case(kind))
+                        {
+                            1
+                        } else if range == "*/*" {
+                            0
+                        } else {
+                            return None;
+                        };
+                        Some((specificity, q))
+                    })
+                    .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+                    .filter(|&(_, q)| q > 0.0)
+                    .map(|(specificity, q)| (format, q, specificity))
+            })
+            .reduce(
+                |best, candidate| match (candidate.1, candidate.2) > (best.1, best.2) {
+                    true => candidate,
+                    false => best,
+                },
+            )
+            .map(|(format, _, _)| format)
+    }
+}
+
+// Weak as the same tag is used regardless of content encoding
+fn etag(buffer: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    buffer.hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+// Removes the exemplar trailing each sample, returning `None` if there are none to remove
+/// Converts the OpenMetrics encoding to the legacy Prometheus text format, which has no `# EOF`
+/// terminator or exemplar syntax.
+pub(crate) fn to_prometheus_text(mut buffer: Bytes) -> Bytes {
+    if buffer.ends_with(b"# EOF\n") {
+        buffer.truncate(buffer.len() - b"# EOF\n".len());
+    }
+    strip_exemplars(&buffer).unwrap_or(buffer)
+}
+
+fn strip_exemplars(buffer: &[u8]) -> Option<Bytes> {
+    let text = std::str::from_utf8(buffer).ok()?;
+    if !text.contains(" # {") {
+        return None;
+    }
+
+    let mut stripped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if line.starts_with('#') {
+            stripped.push_str(line);
+            continue;
+        }
+
+        // Label values may themselves contain " # ", so only look outside of quotes
+        let mut quoted = false;
+        let mut escaped = false;
+        let end = line.char_indices().find_map(|(i, c)| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                '#' if !quoted && line[..i].ends_with(' ') => return Some(i - 1),
+                _ => {}
+            }
+            None
+        });
+
+        match end {
+            Some(end) => {
+                stripped.push_str(&line[..end]);
+                stripped.push('\n');
+            }
+            None => stripped.push_str(line),
+        }
+    }
+
+    Some(stripped.into())
+}
+
+fn matches_etag(req: &Request<Body>, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.trim().trim_start_matches("W/")
+    }
+
+    req.headers()
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AlwaysReady;
+    use prometheus_client::metrics::exemplar::CounterWithExemplar;
+    use tower::Service;
+
+    async fn get<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize>(
+        service: &mut WatcherService<C>,
+        path: &str,
+    ) -> Response<Body> {
+        let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+        service.call(req).await.unwrap()
+    }
+
+    async fn body_string(response: Response<Body>) -> String {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn service(config: Config) -> WatcherService<AlwaysReady> {
+        WatcherService::new(
+            Arc::new(RwLock::new(Registry::default())),
+            ReadinessProbe::default(),
+            config,
+            HttpMetrics::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        let mut service = service(Config::default());
+
+        let response = get(&mut service, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "");
+
+        let response = get(&mut service, "/ready").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            r#"{"ready":true,"not_ready":[],"conditions":{}}"#
+        );
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "alive");
+
+        let response = get(&mut service, "/startup").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "started");
+
+        let response = get(&mut service, "/nope").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_index() {
+        let mut config = Config::default();
+        config.routes.insert(
+            "/debug".into(),
+            Arc::new(|_| Response::new(Body::from("debug"))),
+        );
+        let mut enabled = service(config.clone());
+
+        let response = get(&mut enabled, "/").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_string(response).await;
+        assert!(body.contains("<a href=\"/metrics\">/metrics</a>"));
+        assert!(body.contains("<a href=\"/debug\">/debug</a>"));
+
+        let req = Request::post("/").body(Body::empty()).unwrap();
+        let response = enabled.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+
+        config.disable_index = true;
+        let mut disabled = service(config);
+        let response = get(&mut disabled, "/").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let req = Request::post("/").body(Body::empty()).unwrap();
+        let response = disabled.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_ready_retry_after() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Database,
+        }
+
+        let readiness_probe = ReadinessProbe::<ReadinessConditions>::default();
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(Registry::default())),
+            readiness_probe.clone(),
+            Config {
+                ready_retry_after: Some(Duration::from_millis(1500)),
+                ..Default::default()
+            },
+            HttpMetrics::default(),
+        );
+
+        let req = || {
+            Request::builder()
+                .uri("/ready")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = service.call(req()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+
+        readiness_probe.mark_ready(ReadinessConditions::Database);
+        let response = service.call(req()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let mut config = Config::default();
+        config
+            .request_timeouts
+            .insert("/slow".into(), Duration::from_millis(10));
+        config.routes.insert(
+            "/slow".into(),
+            Arc::new(|_| {
+                std::thread::sleep(Duration::from_millis(100));
+                Response::new(Body::from("slow"))
+            }),
+        );
+        // Routes without a timeout are handled on the thread serving the connection
+        let thread = std::thread::current().id();
+        config.routes.insert(
+            "/inline".into(),
+            Arc::new(move |_| {
+                Response::new(Body::from(
+                    (std::thread::current().id() == thread).to_string(),
+                ))
+            }),
+        );
+        let mut service = service(config);
+
+        let start = Instant::now();
+        let response = get(&mut service, "/slow").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(body_string(response).await, "Request timed out");
+
+        let response = get(&mut service, "/inline").await;
+        assert_eq!(body_string(response).await, "true");
+    }
+
+    #[tokio::test]
+    async fn test_ready_format() {
+        let mut service = service(Config {
+            ready_pretty: true,
+            ready_content_type: Some("application/json; charset=utf-8".into()),
+            ..Default::default()
+        });
+
+        let response = get(&mut service, "/ready").await;
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "application/json; charset=utf-8"
+        );
+        assert_eq!(
+            body_string(response).await,
+            "{\n  \"ready\": true,\n  \"not_ready\": [],\n  \"conditions\": {}\n}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_serialize_error() {
+        #[derive(Clone, PartialEq, Eq, Hash)]
+        struct Broken;
+
+        impl Serialize for Broken {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("broken"))
+            }
+        }
+
+        let readiness_probe = ReadinessProbe::dynamic();
+        readiness_probe.register_condition(Broken);
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(Registry::default())),
+            readiness_probe.clone(),
+            Config::default(),
+            HttpMetrics::default(),
+        );
+
+        let req = Request::builder()
+            .uri("/ready")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body_string(response).await, r#"{"ready":false}"#);
+
+        readiness_probe.mark_ready(Broken);
+        let req = Request::builder()
+            .uri("/ready")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, r#"{"ready":true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_methods() {
+        let mut service = service(Config::default());
+
+        let mut call = |method: Method, path: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap();
+            service.call(req)
+        };
+
+        let response = call(Method::HEAD, "/alive").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = call(Method::POST, "/metrics").await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+
+        let response = call(Method::DELETE, "/ready").await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let response = call(Method::POST, "/nope").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_custom_routes() {
+        let mut config = Config::default();
+        let handler: RouteHandler = Arc::new(|req: Request<Body>| {
+            Response::new(Body::from(format!("custom {}", req.uri().path())))
+        });
+        config
+            .routes
+            .insert("/debug/config".into(), handler.clone());
+        config.routes.insert("/alive".into(), handler);
+        let mut service = service(config);
+
+        let response = get(&mut service, "/debug/config").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "custom /debug/config");
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(body_string(response).await, "alive");
+
+        let response = get(&mut service, "/debug").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_allowlist() {
+        let service = service(Config {
+            metrics_allowlist: Some(vec![
+                "10.0.0.0/8".parse().unwrap(),
+                "::1/128".parse().unwrap(),
+            ]),
+            ..Default::default()
+        });
+        let from = |remote_addr: Option<&str>| {
+            service
+                .clone()
+                .with_connection(ConnectionInfo::new(remote_addr.map(|a| a.parse().unwrap())))
+        };
+
+        let response = get(&mut from(Some("10.1.2.3:1234")), "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get(&mut from(Some("[::ffff:10.1.2.3]:1234")), "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get(&mut from(Some("[::1]:1234")), "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get(&mut from(Some("192.168.0.1:1234")), "/metrics").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = get(&mut from(None), "/metrics").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = get(&mut from(Some("192.168.0.1:1234")), "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let mut cors = service(Config {
+            cors_allowed_origins: vec!["https://dashboard.example.com".into()],
+            ..Default::default()
+        });
+
+        let mut call = |method: Method, origin: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri("/ready")
+                .header(ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap();
+            cors.call(req)
+        };
+
+        let response = call(Method::GET, "https://dashboard.example.com")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example.com"
+        );
+
+        let response = call(Method::OPTIONS, "https://dashboard.example.com")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, HEAD"
+        );
+        assert!(response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let response = call(Method::GET, "https://evil.example.com").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let mut service = service(Config::default());
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/ready")
+            .header(ORIGIN, "https://dashboard.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let mut service = service(Config {
+            bearer_token: Some("s3cret".into()),
+            ..Default::default()
+        });
+
+        let response = get(&mut service, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        for (authorization, status) in [
+            ("Bearer s3cret", StatusCode::OK),
+            ("bearer s3cret", StatusCode::OK),
+            ("BEARER s3cret", StatusCode::OK),
+            ("Bearer wrong", StatusCode::UNAUTHORIZED),
+            ("Basic s3cret", StatusCode::UNAUTHORIZED),
+            ("Bearers3cret", StatusCode::UNAUTHORIZED),
+        ] {
+            let req = Request::builder()
+                .uri("/metrics")
+                .header(AUTHORIZATION, authorization)
+                .body(Body::empty())
+                .unwrap();
+            let response = service.call(req).await.unwrap();
+            assert_eq!(response.status(), status, "{authorization}");
+        }
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_gzip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mut service = service(Config::default());
+
+        let response = get(&mut service, "/metrics").await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .header(ACCEPT_ENCODING, "deflate, gzip;q=0.8")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let mut body = String::new();
+        GzDecoder::new(&bytes[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "");
+    }
+
+    #[tokio::test]
+    async fn test_http_metrics() {
+        let mut registry = Registry::default();
+        let http_metrics = HttpMetrics::default();
+        http_metrics.register(&mut registry);
+
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(registry)),
+            ReadinessProbe::<AlwaysReady>::default(),
+            Config::default(),
+            http_metrics.clone(),
+        );
+
+        get(&mut service, "/alive").await;
+        get(&mut service, "/alive").await;
+        get(&mut service, "/nope").await;
+        get(&mut service, "/nope/again").await;
+
+        let body = body_string(get(&mut service, "/metrics").await).await;
+        assert!(body.contains("watcher_http_requests_total{path=\"/alive\",status=\"200\"} 2\n"));
+        assert!(body.contains("watcher_http_requests_total{path=\"other\",status=\"404\"} 2\n"));
+        assert!(body.contains("watcher_http_request_duration_seconds_count 4\n"));
+        assert!(body.contains("watcher_http_requests_in_flight 1\n"));
+        assert_eq!(http_metrics.requests_in_flight.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_metrics() {
+        let mut registry = Registry::default();
+        let http_metrics = HttpMetrics::default();
+        http_metrics.register(&mut registry);
+
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(registry)),
+            ReadinessProbe::<AlwaysReady>::default(),
+            Config::default(),
+            http_metrics.clone(),
+        );
+        assert_eq!(http_metrics.last_scrape.get(), 0.0);
+
+        get(&mut service, "/metrics").await;
+        let body = body_string(get(&mut service, "/metrics").await).await;
+        assert!(body.contains("watcher_scrape_duration_seconds_count 1\n"));
+        assert!(http_metrics.last_scrape.get() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_cache() {
+        let registry = Arc::new(RwLock::new(Registry::default()));
+        let gauge = Gauge::<i64>::default();
+        registry
+            .write()
+            .unwrap()
+            .register("value", "A value", gauge.clone());
+
+        let service = |ttl| {
+            WatcherService::new(
+                registry.clone(),
+                ReadinessProbe::<AlwaysReady>::default(),
+                Config {
+                    metrics_cache_ttl: ttl,
+                    ..Default::default()
+                },
+                HttpMetrics::default(),
+            )
+        };
+
+        let mut uncached = service(Duration::ZERO);
+        let mut cached = service(Duration::from_secs(60));
+
+        gauge.set(1);
+        assert!(body_string(get(&mut uncached, "/metrics").await)
+            .await
+            .contains("value 1\n"));
+        assert!(body_string(get(&mut cached, "/metrics").await)
+            .await
+            .contains("value 1\n"));
+
+        gauge.set(2);
+        assert!(body_string(get(&mut uncached, "/metrics").await)
+            .await
+            .contains("value 2\n"));
+        assert!(body_string(get(&mut cached, "/metrics").await)
+            .await
+            .contains("value 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_encode_error() {
+        use prometheus_client::encoding::{EncodeMetric, MetricEncoder};
+        use prometheus_client::metrics::MetricType;
+
+        #[derive(Debug)]
+        struct Broken;
+
+        impl EncodeMetric for Broken {
+            fn encode(&self, _encoder: MetricEncoder<'_, '_>) -> Result<(), std::fmt::Error> {
+                Err(std::fmt::Error)
+            }
+
+            fn metric_type(&self) -> MetricType {
+                MetricType::Gauge
+            }
+        }
+
+        let mut registry = Registry::default();
+        registry.register("broken", "A metric that fails to encode", Broken);
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(registry)),
+            ReadinessProbe::<AlwaysReady>::default(),
+            Config::default(),
+            HttpMetrics::default(),
+        );
+
+        let response = get(&mut service, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body_string(response).await, "Internal server error");
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_etag() {
+        let mut uncached = service(Config::default());
+        let response = get(&mut uncached, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(ETAG));
+
+        let mut service = service(Config {
+            metrics_cache_ttl: Duration::from_secs(60),
+            ..Default::default()
+        });
+
+        let response = get(&mut service, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].clone();
+
+        let mut if_none_match = |value: &str| {
+            let req = Request::builder()
+                .uri("/metrics")
+                .header(IF_NONE_MATCH, value)
+                .body(Body::empty())
+                .unwrap();
+            service.call(req)
+        };
+
+        let response = if_none_match(etag.to_str().unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag);
+        assert_eq!(body_string(response).await, "");
+
+        let response = if_none_match("\"other\", W/\"tags\"").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_json() {
+        let mut unconfigured = service(Config::default());
+        let response = get(&mut unconfigured, "/metrics.json").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let config = Config {
+            paths: Paths {
+                metrics_json: Some("/metrics.json".into()),
+                ..Default::default()
+            },
+            bearer_token: Some("s3cret".into()),
+            ..Default::default()
+        };
+        let mut service = service(config);
+        service.metrics_registry.write().unwrap().register(
+            "things",
+            "Some things",
+            Gauge::<i64>::default(),
+        );
+
+        let response = get(&mut service, "/metrics.json").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder()
+            .uri("/metrics.json")
+            .header(AUTHORIZATION, "Bearer s3cret")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            body_string(response).await,
+            r#"[{"name":"things","type":"gauge","labels":{},"value":0.0}]"#
+        );
+
+        let response = get(&mut service, "/").await;
+        assert!(body_string(response).await.contains("/metrics.json"));
+    }
+
+    #[tokio::test]
+    async fn test_version() {
+        let mut unconfigured = service(Config::default());
+        let response = get(&mut unconfigured, "/version").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut service = service(Config {
+            paths: Paths {
+                version: Some("/version".into()),
+                ..Default::default()
+            },
+            version_info: vec![
+                ("version".into(), "1.2.3".into()),
+                ("commit".into(), "abcd".into()),
+            ],
+            ..Default::default()
+        });
+
+        let response = get(&mut service, "/version").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            body_string(response).await,
+            r#"{"commit":"abcd","version":"1.2.3"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Database,
+        }
+
+        let mut unconfigured = service(Config::default());
+        let response = get(&mut unconfigured, "/healthz").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let config = Config {
+            paths: Paths {
+                health: Some("/healthz".into()),
+                ..Default::default()
+            },
+            watchdog_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let watchdog = config.watchdog.clone();
+        let readiness_probe = ReadinessProbe::<ReadinessConditions>::default();
+        let mut service = WatcherService::new(
+            Arc::new(RwLock::new(Registry::default())),
+            readiness_probe.clone(),
+            config,
+            HttpMetrics::default(),
+        );
+
+        let response = get(&mut service, "/healthz").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            body_string(response).await,
+            r#"{"alive":true,"ready":false}"#
+        );
+
+        readiness_probe.mark_ready(ReadinessConditions::Database);
+        let response = get(&mut service, "/healthz").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            r#"{"alive":true,"ready":true}"#
+        );
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let response = get(&mut service, "/healthz").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body_string(response).await,
+            r#"{"alive":false,"ready":true}"#
+        );
+
+        watchdog.pet();
+        let response = get(&mut service, "/healthz").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_liveness() {
+        let wedged = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut service = service(Config {
+            liveness_response: LivenessResponse {
+                status: StatusCode::NO_CONTENT,
+                body: Bytes::new(),
+                content_type: "application/json".into(),
+            },
+            liveness_checks: vec![{
+                let wedged = wedged.clone();
+                Arc::new(move || !wedged.load(std::sync::atomic::Ordering::Relaxed))
+            }],
+            ..Default::default()
+        });
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(body_string(response).await, "");
+
+        wedged.store(true, std::sync::atomic::Ordering::Relaxed);
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body_string(response).await, "not alive");
+    }
+
+    #[tokio::test]
+    async fn test_watchdog() {
+        let config = Config {
+            watchdog_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let watchdog = config.watchdog.clone();
+        let mut service = service(config);
+
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        watchdog.pet();
+        let response = get(&mut service, "/alive").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_openmetrics() {
+        let mut service = service(Config::default());
+
+        let response = get(&mut service, "/metrics").await;
+        assert_eq!(response.headers()[CONTENT_TYPE], PROMETHEUS_CONTENT_TYPE);
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .header(
+                ACCEPT,
+                "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], OPENMETRICS_CONTENT_TYPE);
+        assert_eq!(body_string(response).await, "# EOF\n");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_exemplars() {
+        let mut service = service(Config::default());
+        let requests = CounterWithExemplar::<Vec<(String, String)>>::default();
+        requests.inc_by(1, Some(vec![("trace_id".into(), "abc123".into())]));
+        service
+            .metrics_registry
+            .write()
+            .unwrap()
+            .register("requests", "Requests", requests);
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .header(ACCEPT, "application/openmetrics-text")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        let body = body_string(response).await;
+        assert!(body.contains("requests_total 1 # {trace_id=\"abc123\"} 1.0"));
+
+        let body = body_string(get(&mut service, "/metrics").await).await;
+        assert!(body.contains("requests_total 1\n"));
+        assert!(!body.contains("trace_id"));
+    }
+
+    #[test]
+    fn test_strip_exemplars() {
+        assert_eq!(strip_exemplars(b"up 1\n"), None);
+        assert_eq!(
+            strip_exemplars(
+                b"# HELP a Has # {x} in help\na_total 1 # {t=\"1\"} 1.0\nb{l=\"a # {\\\" # \"} 2\n"
+            )
+            .unwrap(),
+            "# HELP a Has # {x} in help\na_total 1\nb{l=\"a # {\\\" # \"} 2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics_protobuf() {
+        let mut service = service(Config::default());
+        let req = |accept: &str| {
+            Request::builder()
+                .uri("/metrics")
+                .header(ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = service
+            .call(req(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let response = service
+            .call(req(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,text/plain;version=0.0.4;q=0.3,*/*;q=0.1",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROMETHEUS_CONTENT_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        let mut config = Config::default();
+        config.routes.insert(
+            "/upload".into(),
+            Arc::new(|_| Response::new(Body::from("uploaded"))),
+        );
+        config.request_limits.max_body_size = Some(4);
+        config.request_limits.max_headers = Some(2);
+        let mut service = service(config);
+
+        let req = |length: usize| {
+            Request::post("/upload")
+                .header(CONTENT_LENGTH, length)
+                .body(Body::from(vec![b'x'; length]))
+                .unwrap()
+        };
+
+        let response = service.call(req(4)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = service.call(req(5)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut request = req(4);
+        request
+            .headers_mut()
+            .insert("x-one", HeaderValue::from_static("1"));
+        let response = service.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut request = req(4);
+        request
+            .headers_mut()
+            .insert("x-one", HeaderValue::from_static("1"));
+        request
+            .headers_mut()
+            .insert("x-two", HeaderValue::from_static("2"));
+        let response = service.call(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_limits_chunked() {
+        let mut config = Config::default();
+        config.routes.insert(
+            "/echo".into(),
+            Arc::new(|req: Request<Body>| Response::new(req.into_body())),
+        );
+        config.request_limits.max_body_size = Some(4);
+        let mut service = service(config);
+
+        let req = |chunks: &'static [&'static str]| {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    if sender
+                        .send_data(Bytes::from_static(chunk.as_bytes()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            Request::post("/echo").body(body).unwrap()
+        };
+
+        let response = service.call(req(&["xx", "xx"])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "xxxx");
+
+        let response = service.call(req(&["xx", "xx", "x"])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_access_log_message() {
+        assert_eq!(
+            access_log_message(
+                &Method::GET,
+                "/metrics",
+                200,
+                Some(512),
+                Duration::from_millis(3),
+                Some("10.0.0.1:4321".parse().unwrap()),
+            ),
+            "10.0.0.1:4321 \"GET /metrics\" 200 512 3ms"
+        );
+        assert_eq!(
+            access_log_message(&Method::POST, "/", 404, None, Duration::ZERO, None),
+            "- \"POST /\" 404 - 0ns"
+        );
+    }
+
+    #[test]
+    fn test_negotiate_metrics_format() {
+        let negotiate = |accept: &str| {
+            MetricsFormat::negotiate(
+                &Request::builder()
+                    .header(ACCEPT, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(
+            MetricsFormat::negotiate(&Request::new(Body::empty())),
+            Some(MetricsFormat::Prometheus)
+        );
+        assert_eq!(negotiate("*/*"), Some(MetricsFormat::Prometheus));
+        assert_eq!(negotiate("text/*"), Some(MetricsFormat::Prometheus));
+        assert_eq!(negotiate("application/json"), Some(MetricsFormat::Json));
+        assert_eq!(
+            negotiate("application/json, */*"),
+            Some(MetricsFormat::Json)
+        );
+        assert_eq!(
+            negotiate("application/json;q=0.5, text/plain"),
+            Some(MetricsFormat::Prometheus)
+        );
+        assert_eq!(
+            negotiate("application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1"),
+            Some(MetricsFormat::OpenMetrics)
+        );
+        assert_eq!(
+            negotiate("text/plain;q=0, */*"),
+            Some(MetricsFormat::OpenMetrics)
+        );
+        assert_eq!(negotiate("text/html"), None);
+        assert_eq!(negotiate("application/json;q=0"), None);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_accept_json() {
+        let mut service = service(Config::default());
+        service.metrics_registry.write().unwrap().register(
+            "things",
+            "Some things",
+            Gauge::<i64>::default(),
+        );
+
+        let response = service
+            .call(
+                Request::builder()
+                    .uri("/metrics")
+                    .header(ACCEPT, "application/json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body = body_string(response).await;
+        assert!(
+            body.contains("{\"name\":\"things\",\"type\":\"gauge\",\"labels\":{},\"value\":0.0}")
+        );
+
+        let response = service
+            .call(
+                Request::builder()
+                    .uri("/metrics")
+                    .header(ACCEPT, "text/html")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[test]
+    fn test_accepts() {
+        let request = |header: &str| {
+            Request::builder()
+                .header(ACCEPT_ENCODING, header)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let accepts_gzip = |req: &Request<Body>| accepts(req, ACCEPT_ENCODING, "gzip");
+
+        assert!(!accepts_gzip(&Request::new(Body::empty())));
+        assert!(accepts_gzip(&request("gzip")));
+        assert!(accepts_gzip(&request("br, GZIP")));
+        assert!(accepts_gzip(&request("gzip; q=0.5")));
+        assert!(!accepts_gzip(&request("gzip;q=0")));
+        assert!(!accepts_gzip(&request("deflate, br")));
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_router() {
+        let mut config = Config::default();
+        config.paths.alive = "/healthy".into();
+        let mut router = service(config).into_router();
+
+        let mut call = |path: &str| {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            router.call(req)
+        };
+
+        let response = call("/metrics").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROMETHEUS_CONTENT_TYPE);
+
+        let response = call("/ready").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        let response = call("/healthy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "alive");
+
+        let response = call("/alive").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let request = |header: Option<&str>| {
+            let mut builder = Request::builder();
+            if let Some(header) = header {
+                builder = builder.header(AUTHORIZATION, header);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        assert!(is_authorized(&request(None), None));
+        assert!(is_authorized(&request(Some("Bearer abc")), None));
+
+        assert!(is_authorized(
+            &request(Some("Bearer s3cret")),
+            Some("s3cret")
+        ));
+        assert!(!is_authorized(&request(None), Some("s3cret")));
+        assert!(!is_authorized(
+            &request(Some("Bearer s3cre")),
+            Some("s3cret")
+        ));
+        assert!(!is_authorized(
+            &request(Some("Bearer s3creT")),
+            Some("s3cret")
+        ));
+        assert!(!is_authorized(
+            &request(Some("Basic s3cret")),
+            Some("s3cret")
+        ));
+    }
+}
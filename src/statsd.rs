@@ -0,0 +1,128 @@
+use super::metrics_json::{self, Sample};
+use anyhow::Result;
+use std::collections::HashMap;
+
+// Keeps each datagram within the payload of a single Ethernet frame
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+/// Converts metrics encoded in the text format into DogStatsD lines.
+///
+/// Counters and histograms are cumulative, so they are sent as counters of the change since the
+/// previous encoding. Everything else is sent as a gauge.
+#[derive(Default)]
+pub(crate) struct StatsdEncoder {
+    previous: HashMap<String, f64>,
+}
+
+impl StatsdEncoder {
+    pub(crate) fn encode(&mut self, text: &str) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        for sample in metrics_json::parse(text)? {
+            if sample.name.ends_with("_created") {
+                continue;
+            }
+
+            let tags = tags(&sample);
+            match sample.metric_type {
+                "counter" | "histogram" => {
+                    let key = format!("{}{}", sample.name, tags);
+                    let previous = self.previous.insert(key, sample.value).unwrap_or(0.0);
+                    // A decrease means the counter was reset, so everything it holds is new
+                    let delta = match sample.value >= previous {
+                        true => sample.value - previous,
+                        false => sample.value,
+                    };
+                    if delta > 0.0 {
+                        lines.push(format!("{}:{}|c{}", sample.name, delta, tags));
+                    }
+                }
+                _ => lines.push(format!("{}:{}|g{}", sample.name, sample.value, tags)),
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+fn tags(sample: &Sample) -> String {
+    if sample.labels.is_empty() {
+        return String::new();
+    }
+
+    let sanitise = |s: &str| s.replace(['|', ',', '#', '\n'], "_");
+    let tags: Vec<String> = sample
+        .labels
+        .iter()
+        .map(|(key, value)| format!("{}:{}", sanitise(key), sanitise(value)))
+        .collect();
+    format!("|#{}", tags.join(","))
+}
+
+/// Groups `lines` into newline separated datagrams no larger than the maximum datagram size.
+pub(crate) fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut datagram = String::new();
+
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM_SIZE {
+            datagrams.push(std::mem::take(&mut datagram));
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram.push_str(line);
+    }
+
+    if !datagram.is_empty() {
+        datagrams.push(datagram);
+    }
+    datagrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let text = |requests: u64| {
+            format!(
+                "# TYPE up gauge\nup 1\n\
+                 # TYPE requests counter\n\
+                 requests_total{{path=\"/a|b\"}} {requests}\n\
+                 requests_created{{path=\"/a|b\"}} 1700000000.0\n# EOF\n"
+            )
+        };
+
+        let mut encoder = StatsdEncoder::default();
+        assert_eq!(
+            encoder.encode(&text(3)).unwrap(),
+            vec!["up:1|g", "requests_total:3|c|#path:/a_b"]
+        );
+        assert_eq!(
+            encoder.encode(&text(5)).unwrap(),
+            vec!["up:1|g", "requests_total:2|c|#path:/a_b"]
+        );
+        assert_eq!(encoder.encode(&text(5)).unwrap(), vec!["up:1|g"]);
+        assert_eq!(
+            encoder.encode(&text(1)).unwrap(),
+            vec!["up:1|g", "requests_total:1|c|#path:/a_b"]
+        );
+    }
+
+    #[test]
+    fn test_datagrams() {
+        assert!(datagrams(&[]).is_empty());
+        assert_eq!(
+            datagrams(&["a:1|g".into(), "b:2|g".into()]),
+            vec!["a:1|g\nb:2|g"]
+        );
+
+        let long = "x".repeat(1000);
+        assert_eq!(
+            datagrams(&[long.clone(), long.clone()]),
+            vec![long.clone(), long]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use super::{metrics_json, watcher::Watcher};
+use anyhow::{bail, Result};
+use hyper::{
+    body,
+    client::HttpConnector,
+    header::{HeaderName, HeaderValue, ACCEPT, AUTHORIZATION},
+    http::request,
+    Body, Client, HeaderMap, Request, Response, StatusCode,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, hash::Hash, io, net::SocketAddr};
+
+/// Serves a [`Watcher`] on an ephemeral local port, for making assertions against it in tests.
+///
+/// The server, along with any others started by the watcher, is stopped when this is dropped.
+pub struct TestWatcher<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> {
+    watcher: Watcher<C>,
+    address: SocketAddr,
+    client: Client<HttpConnector>,
+    headers: HeaderMap,
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> TestWatcher<C> {
+    pub async fn start(mut watcher: Watcher<C>) -> io::Result<Self> {
+        let (address, _) = watcher
+            .start_server(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await?;
+
+        Ok(Self {
+            watcher,
+            address,
+            client: Client::new(),
+            headers: HeaderMap::new(),
+        })
+    }
+
+    /// Sends `value` as the `name` header with every request.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Authenticates every request with `token`, for watchers requiring a bearer token.
+    pub fn with_bearer_token(self, token: &str) -> Result<Self> {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+        Ok(self.with_header(AUTHORIZATION, value))
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn watcher(&self) -> &Watcher<C> {
+        &self.watcher
+    }
+
+    /// Starts building a GET request for `path`, including the headers sent with every request.
+    pub fn request(&self, path: &str) -> request::Builder {
+        let mut builder = Request::get(format!("http://{}{}", self.address, path));
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder
+    }
+
+    pub async fn send(&self, request: Request<Body>) -> Result<Response<Body>> {
+        Ok(self.client.request(request).await?)
+    }
+
+    /// Requests `path`, returning the status and body of the response.
+    pub async fn get(&self, path: &str) -> Result<(StatusCode, String)> {
+        self.get_with(self.request(path)).await
+    }
+
+    async fn get_with(&self, request: request::Builder) -> Result<(StatusCode, String)> {
+        let response = self.send(request.body(Body::empty())?).await?;
+        let status = response.status();
+        let body = body::to_bytes(response.into_body()).await?;
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    /// Whether the readiness endpoint reports the watcher as ready.
+    pub async fn get_ready(&self) -> Result<bool> {
+        match self.get(&self.watcher.config.paths.ready).await?.0 {
+            StatusCode::OK => Ok(true),
+            StatusCode::SERVICE_UNAVAILABLE => Ok(false),
+            status => bail!("Unexpected readiness response status: {}", status),
+        }
+    }
+
+    /// Scrapes the metrics endpoint, returning the value of each sample keyed by its name and
+    /// labels, e.g. `ready{condition="Database"}`.
+    pub async fn get_metrics(&self) -> Result<BTreeMap<String, f64>> {
+        // Requested explicitly, in case the watcher defaults to another format
+        let request = self
+            .request(&self.watcher.config.paths.metrics)
+            .header(ACCEPT, "application/openmetrics-text");
+        let (status, body) = self.get_with(request).await?;
+        if status != StatusCode::OK {
+            bail!("Unexpected metrics response status: {}", status);
+        }
+
+        Ok(metrics_json::parse(&body)?
+            .into_iter()
+            .map(|sample| {
+                let labels: Vec<String> = sample
+                    .labels
+                    .iter()
+                    .map(|(key, value)| format!("{}=\"{}\"", key, value))
+                    .collect();
+                let key = match labels.is_empty() {
+                    true => sample.name.to_owned(),
+                    false => format!("{}{{{}}}", sample.name, labels.join(",")),
+                };
+                (key, sample.value)
+            })
+            .collect())
+    }
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> Drop for TestWatcher<C> {
+    fn drop(&mut self) {
+        self.watcher.stop_server().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AlwaysReady;
+
+    #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+    enum ReadinessConditions {
+        Database,
+    }
+
+    #[tokio::test]
+    async fn test_test_watcher() {
+        let test = TestWatcher::start(Watcher::<ReadinessConditions>::default())
+            .await
+            .unwrap();
+        assert!(test.address().ip().is_loopback());
+        assert_ne!(test.address().port(), 0);
+
+        assert!(!test.get_ready().await.unwrap());
+        let metrics = test.get_metrics().await.unwrap();
+        assert_eq!(metrics["up"], 0.0);
+        assert_eq!(metrics["ready{condition=\"Database\"}"], 0.0);
+
+        test.watcher()
+            .shared_readiness_probe()
+            .mark_ready(ReadinessConditions::Database);
+        assert!(test.get_ready().await.unwrap());
+        let metrics = test.get_metrics().await.unwrap();
+        assert_eq!(metrics["up"], 1.0);
+        assert_eq!(metrics["ready{condition=\"Database\"}"], 1.0);
+
+        let (status, body) = test.get("/alive").await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "alive");
+    }
+
+    #[tokio::test]
+    async fn test_test_watcher_bearer_token() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.require_bearer_token("s3cret".into());
+
+        let test = TestWatcher::start(watcher.clone()).await.unwrap();
+        assert!(test.get_metrics().await.is_err());
+
+        let test = TestWatcher::start(watcher)
+            .await
+            .unwrap()
+            .with_bearer_token("s3cret")
+            .unwrap();
+        assert_eq!(test.get_metrics().await.unwrap()["up"], 1.0);
+
+        let response = test
+            .send(test.request("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
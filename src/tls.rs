@@ -0,0 +1,93 @@
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hyper::server::accept::Accept;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{
+    rustls::{Certificate, ServerConfig},
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+/// The certificate chain presented by a TLS client, available as a request extension to custom
+/// route handlers.
+#[derive(Clone, Debug)]
+pub struct ClientCertificates(pub Vec<Certificate>);
+
+type Handshake<C> = Pin<Box<dyn Future<Output = io::Result<TlsStream<C>>> + Send>>;
+
+pub(crate) struct TlsIncoming<I: Accept> {
+    incoming: I,
+    acceptor: TlsAcceptor,
+    handshake_timeout: Option<Duration>,
+    handshakes: FuturesUnordered<Handshake<I::Conn>>,
+}
+
+impl<I: Accept> TlsIncoming<I> {
+    pub(crate) fn new(
+        incoming: I,
+        config: ServerConfig,
+        handshake_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            incoming,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            handshake_timeout,
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl<I> Accept for TlsIncoming<I>
+where
+    I: Accept + Unpin,
+    I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Conn = TlsStream<I::Conn>;
+    type Error = I::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+
+        // Accept every pending connection and start its handshake, handshakes are driven
+        // concurrently so that a slow client does not block others from connecting
+        loop {
+            match Pin::new(&mut this.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    let handshake = this.acceptor.accept(stream);
+                    let timeout = this.handshake_timeout;
+                    this.handshakes.push(Box::pin(async move {
+                        match timeout {
+                            Some(timeout) => tokio::time::timeout(timeout, handshake)
+                                .await
+                                .map_err(|_| {
+                                    io::Error::new(io::ErrorKind::TimedOut, "Handshake timed out")
+                                })?,
+                            None => handshake.await,
+                        }
+                    }));
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            match this.handshakes.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(stream))) => return Poll::Ready(Some(Ok(stream))),
+                Poll::Ready(Some(Err(e))) => log::debug!("TLS handshake failed: {}", e),
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use hyper::server::accept::Accept;
+use std::{
+    fs, io,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::{UnixListener, UnixStream};
+
+pub(crate) struct UnixIncoming {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixIncoming {
+    pub(crate) fn bind(path: PathBuf) -> io::Result<Self> {
+        remove_stale_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+}
+
+impl Accept for UnixIncoming {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    }
+}
+
+impl Drop for UnixIncoming {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::debug!("Failed to remove socket {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+// Only a socket without a listener is stale, one that accepts connections is still in use
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use", path.display()),
+                )),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+                Err(e) => Err(e),
+            }
+        }
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind() {
+        let path =
+            std::env::temp_dir().join(format!("kagiyama-test-bind-{}.sock", std::process::id()));
+
+        // Left behind by a listener which is no longer running
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let incoming = UnixIncoming::bind(path.clone()).unwrap();
+        assert_eq!(
+            UnixIncoming::bind(path.clone()).err().unwrap().kind(),
+            io::ErrorKind::AddrInUse
+        );
+        assert!(UnixStream::connect(&path).await.is_ok());
+
+        drop(incoming);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,54 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Handle used to show that the main loop of the application is still making progress.
+///
+/// Once a watchdog timeout is configured the liveness endpoint responds with 503 Service
+/// Unavailable if [`Watchdog::pet`] has not been called within that timeout.
+#[derive(Clone)]
+pub struct Watchdog {
+    epoch: Instant,
+    last_pet: Arc<AtomicU64>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            last_pet: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Watchdog {
+    pub fn pet(&self) {
+        self.last_pet
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn since_last_pet(&self) -> Duration {
+        self.epoch
+            .elapsed()
+            .saturating_sub(Duration::from_millis(self.last_pet.load(Ordering::Relaxed)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pet() {
+        let watchdog = Watchdog::default();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(watchdog.since_last_pet() >= Duration::from_millis(20));
+
+        watchdog.clone().pet();
+        assert!(watchdog.since_last_pet() < Duration::from_millis(20));
+    }
+}
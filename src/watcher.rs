@@ -1,125 +1,2281 @@
-use super::readiness_probe::ReadinessProbe;
+#[cfg(feature = "push")]
+use super::service::{to_prometheus_text, PROMETHEUS_CONTENT_TYPE};
+#[cfg(feature = "statsd")]
+use super::statsd::{self, StatsdEncoder};
+#[cfg(feature = "tls")]
+use super::tls::TlsIncoming;
+#[cfg(all(unix, feature = "uds"))]
+use super::uds::UnixIncoming;
+use super::{
+    builder::WatcherBuilder,
+    encoding::encode_registry,
+    error::AlreadyRegistered,
+    readiness_probe::{ReadinessConditionSet, ReadinessProbe},
+};
+#[cfg(feature = "server")]
+use super::{
+    connection::Connection,
+    error::StopError,
+    limit::{LimitedConn, LimitedIncoming, RequestLimits, ServerLimits},
+    service::{
+        BoxError, BoxedService, Config, HttpMetrics, LivenessResponse, Paths, WatcherService,
+    },
+    watchdog::Watchdog,
+};
+#[cfg(feature = "tls")]
+use crate::rustls;
+#[cfg(feature = "server")]
+use anyhow::Result;
+#[cfg(feature = "push")]
+use hyper::header::CONTENT_TYPE;
+#[cfg(feature = "server")]
 use hyper::{
-    header::CONTENT_TYPE, service::service_fn, Body, Request, Response, Server, StatusCode,
+    body::Bytes,
+    server::{
+        accept::Accept,
+        conn::{AddrIncoming, AddrStream},
+        Builder,
+    },
+    service::make_service_fn,
+    Body, Request, Response, Server, StatusCode,
+};
+#[cfg(feature = "server")]
+use ipnet::IpNet;
+use prometheus_client::{
+    metrics::{family::Family, gauge::Gauge},
+    registry::{Metric, Registry},
 };
-use prometheus_client::encoding::text::encode;
-use prometheus_client::registry::Registry;
 use serde::Serialize;
 use std::{
     cmp::Eq,
+    collections::HashSet,
     hash::Hash,
     marker::{Send, Sync},
-    net::SocketAddr,
-    ops::Deref,
-    sync::{Arc, RwLock},
+    sync::{atomic::AtomicU64, Arc, Mutex, PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "server")]
+use std::{convert::Infallible, future::Future, io, net::SocketAddr, pin::Pin, time::Duration};
+#[cfg(feature = "server")]
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpSocket},
+    runtime::{EnterGuard, Handle},
+    sync::{broadcast, watch},
+    task::JoinHandle,
 };
-use strum::IntoEnumIterator;
-use tokio::{sync::broadcast, task::JoinHandle};
-use tower::make::Shared;
+#[cfg(feature = "server")]
+use tower::{Layer, Service, ServiceExt};
+
+pub(crate) const DEFAULT_UP_METRIC: (&str, &str) = ("up", "Overall system readiness");
+
+#[cfg(feature = "server")]
+const DEFAULT_TERMINATION_SIGNAL_CAPACITY: usize = 16;
 
 #[derive(Clone)]
 pub struct Watcher<C: Hash + Eq + Send + Sync + Serialize> {
     metrics_registry: Arc<RwLock<Registry>>,
+    registered_names: Arc<Mutex<HashSet<String>>>,
     readiness_probe: ReadinessProbe<C>,
+    #[cfg(feature = "server")]
     termination_signal: broadcast::Sender<()>,
+    #[cfg(feature = "server")]
+    running_tasks: Arc<watch::Sender<usize>>,
+    #[cfg(feature = "server")]
+    http_metrics: HttpMetrics,
+    #[cfg(feature = "server")]
+    pub(crate) config: Config,
+    #[cfg(feature = "server")]
+    address: Option<SocketAddr>,
 }
 
-impl<C: 'static + Clone + IntoEnumIterator + Hash + Eq + Sync + Send + Serialize> Default
+impl<C: 'static + Clone + ReadinessConditionSet + Hash + Eq + Sync + Send + Serialize> Default
     for Watcher<C>
 {
     fn default() -> Self {
-        let metrics_registry = Arc::new(RwLock::new(<Registry>::default()));
-        let readiness_probe = ReadinessProbe::default();
-        let (termination_signal, _) = broadcast::channel::<()>(1);
+        Self::with_registry(Registry::default())
+    }
+}
 
-        metrics_registry.write().unwrap().register(
-            "up",
-            "Overall system readiness",
-            readiness_probe.up.clone(),
+impl<C: 'static + Clone + ReadinessConditionSet + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
+    pub fn builder() -> WatcherBuilder<C> {
+        WatcherBuilder::default()
+    }
+
+    pub fn with_registry(registry: Registry) -> Self {
+        Self::new(registry, ReadinessProbe::default(), DEFAULT_UP_METRIC)
+    }
+
+    /// Prefixes the name of every metric, including those registered later, with `prefix`.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self::with_registry(Registry::with_prefix(prefix))
+    }
+
+    /// Adds `labels` to every metric, including those registered later.
+    pub fn with_constant_labels(labels: Vec<(String, String)>) -> Self {
+        Self::with_registry(Registry::with_labels(
+            labels.into_iter().map(|(k, v)| (k.into(), v.into())),
+        ))
+    }
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
+    pub fn with_readiness_probe(readiness_probe: ReadinessProbe<C>) -> Self {
+        Self::new(Registry::default(), readiness_probe, DEFAULT_UP_METRIC)
+    }
+
+    pub(crate) fn new(
+        mut registry: Registry,
+        readiness_probe: ReadinessProbe<C>,
+        (up_name, up_help): (&str, &str),
+    ) -> Self {
+        registry.register(up_name, up_help, readiness_probe.up.clone());
+        registry.register(
+            "readiness_score",
+            "Fraction of the weight of readiness conditions which are ready",
+            readiness_probe.score.clone(),
+        );
+        registry.register(
+            "degraded",
+            "Overall system degradation",
+            readiness_probe.degraded.clone(),
+        );
+        registry.register(
+            "ready",
+            "Readiness of individual conditions",
+            readiness_probe.condition_ready.clone(),
+        );
+        registry.register(
+            "readiness_transitions",
+            "Number of times each readiness condition has changed",
+            readiness_probe.condition_transitions.clone(),
+        );
+        registry.register(
+            "readiness_conditions_total",
+            "Number of readiness conditions",
+            readiness_probe.conditions_total.clone(),
+        );
+        registry.register(
+            "readiness_conditions_ready",
+            "Number of readiness conditions which are ready",
+            readiness_probe.conditions_ready.clone(),
+        );
+        registry.register(
+            "readiness_condition_age_seconds",
+            "Seconds since each readiness condition was last set",
+            readiness_probe.condition_ages(),
+        );
+
+        let start_time = Gauge::<f64, AtomicU64>::default();
+        start_time.set(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+        );
+        registry.register(
+            "process_start_time_seconds",
+            "Start time of the process since unix epoch in seconds",
+            start_time,
         );
 
+        #[cfg_attr(not(feature = "server"), allow(unused_mut))]
+        let mut registered_names: HashSet<String> = [
+            up_name,
+            "readiness_score",
+            "degraded",
+            "ready",
+            "readiness_transitions",
+            "readiness_conditions_total",
+            "readiness_conditions_ready",
+            "readiness_condition_age_seconds",
+            "process_start_time_seconds",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        #[cfg(feature = "server")]
+        let http_metrics = {
+            let http_metrics = HttpMetrics::default();
+            http_metrics.register(&mut registry);
+            registered_names.extend(
+                [
+                    "watcher_http_requests",
+                    "watcher_http_request_duration_seconds",
+                    "watcher_http_requests_in_flight",
+                    "watcher_scrape_duration_seconds",
+                    "watcher_last_scrape_timestamp_seconds",
+                ]
+                .map(String::from),
+            );
+            http_metrics
+        };
+
         Self {
-            metrics_registry,
+            metrics_registry: Arc::new(RwLock::new(registry)),
+            registered_names: Arc::new(Mutex::new(registered_names)),
             readiness_probe,
-            termination_signal,
+            #[cfg(feature = "server")]
+            termination_signal: broadcast::channel(DEFAULT_TERMINATION_SIGNAL_CAPACITY).0,
+            #[cfg(feature = "server")]
+            running_tasks: Arc::new(watch::channel(0).0),
+            #[cfg(feature = "server")]
+            http_metrics,
+            #[cfg(feature = "server")]
+            config: Config::default(),
+            #[cfg(feature = "server")]
+            address: None,
         }
     }
-}
 
-impl<C: 'static + Clone + IntoEnumIterator + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
     pub fn metrics_registry(&self) -> std::sync::RwLockWriteGuard<'_, Registry> {
-        self.metrics_registry.write().unwrap()
+        self.metrics_registry
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 
+    pub fn metrics_registry_read(&self) -> std::sync::RwLockReadGuard<'_, Registry> {
+        self.metrics_registry
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn encode_metrics(&self) -> Result<String, std::fmt::Error> {
+        self.readiness_probe.update_metrics();
+        encode_registry(&self.metrics_registry_read())
+    }
+
+    /// Returns a handle to the watcher's readiness probe, conditions marked through it are seen
+    /// by the watcher and every other handle.
     pub fn readiness_probe(&self) -> ReadinessProbe<C> {
         self.readiness_probe.clone()
     }
 
-    pub async fn start_server(&mut self, address: SocketAddr) -> JoinHandle<()> {
-        let registry = self.metrics_registry.clone();
-        let readiness_conditions = self.readiness_probe.clone();
+    /// Borrows the watcher's readiness probe, for updating conditions without taking a handle.
+    pub fn shared_readiness_probe(&self) -> &ReadinessProbe<C> {
+        &self.readiness_probe
+    }
+
+    /// Reports not ready regardless of the readiness conditions, until [`Watcher::end_drain`] is
+    /// called. Servers keep running, so in-flight work can be completed once load balancers have
+    /// stopped routing traffic here.
+    pub fn begin_drain(&self) {
+        event!(info, {}, "Draining");
+        self.readiness_probe.set_draining(true);
+    }
+
+    pub fn end_drain(&self) {
+        event!(info, {}, "No longer draining");
+        self.readiness_probe.set_draining(false);
+    }
+}
+
+#[cfg(feature = "server")]
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
+    pub(crate) fn with_config(mut self, config: Config, address: Option<SocketAddr>) -> Self {
+        if let Some(capacity) = config.termination_signal_capacity {
+            self.termination_signal = broadcast::channel(capacity).0;
+        }
+        self.config = config;
+        self.address = address;
+        self
+    }
+
+    /// Serves metrics on `path` in the Prometheus text format, or in the OpenMetrics text format
+    /// or as JSON when requested through the `Accept` header. Requests that accept none of these,
+    /// such as those only accepting the protobuf format, receive 406 Not Acceptable.
+    pub fn set_metrics_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.metrics = path.into();
+        self.set_paths(paths)
+    }
+
+    pub fn set_ready_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.ready = path.into();
+        self.set_paths(paths)
+    }
+
+    pub fn set_alive_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.alive = path.into();
+        self.set_paths(paths)
+    }
+
+    pub fn set_startup_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.startup = path.into();
+        self.set_paths(paths)
+    }
+
+    /// Also serves metrics as JSON on `path` for consumers that cannot parse the text format,
+    /// subject to the same access controls as the metrics endpoint.
+    pub fn set_metrics_json_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.metrics_json = Some(path.into());
+        self.set_paths(paths)
+    }
+
+    /// Also serves a combined health check on `path`, responding with 200 OK only when the
+    /// process is both alive and ready.
+    pub fn set_health_path(&mut self, path: impl Into<String>) -> Result<()> {
+        let mut paths = self.config.paths.clone();
+        paths.health = Some(path.into());
+        self.set_paths(paths)
+    }
+
+    /// Serves `info` as a JSON object on `/version`, such as the version and commit of the build.
+    ///
+    /// Fails if `info` is empty.
+    pub fn set_version_info(&mut self, info: Vec<(String, String)>) -> Result<()> {
+        if info.is_empty() {
+            anyhow::bail!("Version info must not be empty");
+        }
+        let mut paths = self.config.paths.clone();
+        paths.version.get_or_insert_with(|| "/version".into());
+        self.set_paths(paths)?;
+        self.config.version_info = info;
+        Ok(())
+    }
+
+    fn set_paths(&mut self, paths: Paths) -> Result<()> {
+        paths.validate()?;
+        self.config.paths = paths;
+        Ok(())
+    }
+
+    pub fn require_bearer_token(&mut self, token: String) {
+        self.config.bearer_token = Some(token);
+    }
+
+    /// Serves `path` with `handler`, requests to servers started by the watcher carry the peer's
+    /// [`SocketAddr`] as a request extension where one is known.
+    pub fn add_route(
+        &mut self,
+        path: String,
+        handler: impl Fn(Request<Body>) -> Response<Body> + Send + Sync + 'static,
+    ) {
+        self.config.routes.insert(path, Arc::new(handler));
+    }
+
+    /// Allows browsers on `origins` to make cross-origin requests to the built in endpoints, an
+    /// origin of `*` allows any.
+    pub fn set_cors_allowed_origins(&mut self, origins: Vec<String>) {
+        self.config.cors_allowed_origins = origins;
+    }
+
+    /// Only serves metrics to peers within one of `cidrs`, others receive 403 Forbidden.
+    ///
+    /// Peer addresses are only known to TCP servers started by the watcher, so metrics requests
+    /// over a Unix socket or through an axum router are always rejected.
+    pub fn allow_metrics_from(&mut self, cidrs: Vec<IpNet>) {
+        self.config.metrics_allowlist = Some(cidrs);
+    }
+
+    pub fn set_liveness_response(
+        &mut self,
+        status: StatusCode,
+        body: impl Into<Bytes>,
+        content_type: impl Into<String>,
+    ) {
+        self.config.liveness_response = LivenessResponse {
+            status,
+            body: body.into(),
+            content_type: content_type.into(),
+        };
+    }
+
+    /// Responds to liveness probes with 503 Service Unavailable whenever `check` returns false.
+    pub fn add_liveness_check(&mut self, check: impl Fn() -> bool + Send + Sync + 'static) {
+        self.config.liveness_checks.push(Arc::new(check));
+    }
+
+    /// Returns a handle that must be petted at least once every `timeout`, as set with
+    /// [`Watcher::set_watchdog_timeout`], for the process to be considered alive.
+    pub fn watchdog(&self) -> Watchdog {
+        self.config.watchdog.clone()
+    }
+
+    pub fn set_watchdog_timeout(&mut self, timeout: Duration) {
+        self.config.watchdog_timeout = Some(timeout);
+    }
+
+    /// Pretty prints the JSON body of readiness responses, for readability when debugging.
+    pub fn set_ready_pretty(&mut self, pretty: bool) {
+        self.config.ready_pretty = pretty;
+    }
+
+    pub fn set_ready_content_type(&mut self, content_type: impl Into<String>) {
+        self.config.ready_content_type = Some(content_type.into());
+    }
+
+    pub fn set_ready_retry_after(&mut self, retry_after: Duration) {
+        self.config.ready_retry_after = Some(retry_after);
+    }
+
+    pub fn set_server_limits(&mut self, limits: ServerLimits) {
+        self.config.server_limits = limits;
+    }
+
+    /// Logs every request at info level with its method, path, status, response size, duration
+    /// and peer address.
+    pub fn enable_access_log(&mut self, enable: bool) {
+        self.config.access_log = enable;
+    }
+
+    pub fn set_request_limits(&mut self, limits: RequestLimits) {
+        self.config.request_limits = limits;
+    }
+
+    /// Sets `SO_REUSEADDR` on listening sockets, so a restarted process can bind its address
+    /// while connections from the previous one are still in `TIME_WAIT`.
+    pub fn set_reuse_address(&mut self, reuse: bool) {
+        self.config.reuse_address = reuse;
+    }
+
+    /// Sets `SO_REUSEPORT` on listening sockets, allowing a new process to bind the address
+    /// while the old one is still serving, the kernel balances connections between them.
+    #[cfg(target_os = "linux")]
+    pub fn set_reuse_port(&mut self, reuse: bool) {
+        self.config.reuse_port = reuse;
+    }
+
+    /// Also accepts HTTP/2 over cleartext (h2c) with prior knowledge, alongside HTTP/1.
+    #[cfg(feature = "http2")]
+    pub fn enable_http2(&mut self, enable: bool) {
+        self.config.http2 = enable;
+    }
+
+    /// Runs servers started by the watcher, their connections, and its background tasks (such as
+    /// readiness checks and metrics pushers) on `runtime` rather than the runtime they are
+    /// started from, isolating them from application work.
+    pub fn set_runtime(&mut self, runtime: Handle) {
+        self.config.runtime = Some(runtime);
+    }
+
+    /// Responds with 503 Service Unavailable to requests for `path`, either a built in or custom
+    /// route, which are not handled within `timeout`.
+    ///
+    /// Requests for the path are handled on Tokio's blocking thread pool. A handler which times
+    /// out is not cancelled, it keeps its thread (and any locks it holds) until it returns.
+    pub fn set_request_timeout(&mut self, path: impl Into<String>, timeout: Duration) {
+        self.config.request_timeouts.insert(path.into(), timeout);
+    }
+
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.config.shutdown_timeout = Some(timeout);
+    }
+
+    pub fn disable_index(&mut self) {
+        self.config.disable_index = true;
+    }
+
+    /// Serves the previously encoded metrics if they are younger than `ttl`, a zero TTL (the
+    /// default) encodes the registry on every request.
+    ///
+    /// A non-zero TTL also enables `ETag` revalidation, scrapers sending the tag of the cached
+    /// metrics in `If-None-Match` receive 304 Not Modified.
+    pub fn set_metrics_cache_ttl(&mut self, ttl: Duration) {
+        self.config.metrics_cache_ttl = ttl;
+    }
+}
+
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
+    /// Registers `metric` unless a metric of the same name has already been registered through
+    /// this method or by the watcher itself.
+    ///
+    /// Metrics registered directly on [`Watcher::metrics_registry`] are not checked.
+    pub fn try_register(
+        &self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        metric: impl Metric,
+    ) -> Result<(), AlreadyRegistered> {
+        let name = name.into();
+        if !self
+            .registered_names
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.clone())
+        {
+            return Err(AlreadyRegistered(name));
+        }
+
+        self.metrics_registry().register(name, help, metric);
+        Ok(())
+    }
+
+    pub fn register_build_info(&mut self, labels: Vec<(String, String)>) {
+        let build_info = Family::<Vec<(String, String)>, Gauge>::default();
+        build_info.get_or_create(&labels).set(1);
+        if let Err(e) = self.try_register("build_info", "Build information", build_info) {
+            log::warn!("Failed to register build info: {}", e);
+        }
+    }
+
+    /// Registers the standard `process_*` metrics, these are only available on Linux.
+    #[cfg(feature = "process")]
+    pub fn register_process_metrics(&mut self) {
+        crate::process::register(&mut self.metrics_registry());
+    }
+}
+
+#[cfg(feature = "server")]
+impl<C: 'static + Clone + Hash + Eq + Sync + Send + Serialize> Watcher<C> {
+    /// Wraps the service handling requests to servers started by this watcher in `layer`, layers
+    /// added later wrap those added before them.
+    ///
+    /// Layers are not applied to [`Watcher::service`] or the axum router.
+    pub fn with_layer<L>(&mut self, layer: L)
+    where
+        L: Layer<BoxedService> + Send + Sync + 'static,
+        L::Service: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+        <L::Service as Service<Request<Body>>>::Future: Send + 'static,
+        <L::Service as Service<Request<Body>>>::Error: Into<BoxError>,
+    {
+        let inner = self.config.layer.take();
+        self.config.layer = Some(Arc::new(move |service| {
+            let service = match &inner {
+                Some(inner) => inner(service),
+                None => service,
+            };
+            BoxedService::new(layer.layer(service).map_err(Into::into))
+        }));
+    }
+
+    pub fn service(&self) -> WatcherService<C> {
+        WatcherService::new(
+            self.metrics_registry.clone(),
+            self.readiness_probe.clone(),
+            self.config.clone(),
+            self.http_metrics.clone(),
+        )
+    }
+
+    #[cfg(feature = "axum")]
+    pub fn router(&self) -> axum::Router {
+        self.service().into_router()
+    }
+
+    pub async fn start(&mut self) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+        match self.address {
+            Some(address) => self.start_server(address).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No server address configured",
+            )),
+        }
+    }
+
+    pub async fn start_server(
+        &mut self,
+        address: SocketAddr,
+    ) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+        let incoming = {
+            let _runtime = self.enter_runtime();
+            bind(address, &self.config)?
+        };
+        let address = incoming.local_addr();
+
+        event!(trace, { %address, }, "Listening on {}", address);
+        Ok((address, self.spawn_server(incoming)))
+    }
+
+    /// Starts a server that runs for as long as the returned guard is held.
+    pub async fn start_server_guard(&mut self, address: SocketAddr) -> io::Result<ServerGuard> {
+        let (address, handle) = self.start_server(address).await?;
+        Ok(ServerGuard {
+            address,
+            termination_signal: self.termination_signal.clone(),
+            handle: Some(handle),
+        })
+    }
+
+    /// Serves on `address`, within a Tokio runtime, until `shutdown` completes.
+    ///
+    /// Unlike the `start_server*` methods this does not spawn a task and is not stopped by
+    /// [`Watcher::stop_server`], the caller drives the returned future and decides when to shut
+    /// down. See [`Watcher::serve_with`] for running on other executors.
+    pub async fn serve(
+        &self,
+        address: SocketAddr,
+        shutdown: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        let incoming = bind(address, &self.config)?;
+        let address = incoming.local_addr();
+        event!(trace, { %address, }, "Listening on {}", address);
+
+        let service = self.service();
+        self.server_builder(
+            incoming,
+            self.config.server_limits.max_connections,
+            self.config.server_limits.header_read_timeout,
+        )
+        .serve(make_service_fn(move |conn: &LimitedConn<AddrStream>| {
+            let service = service.clone().with_connection(conn.info()).into_layered();
+            async move { Ok::<_, Infallible>(service) }
+        }))
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(io::Error::other)
+    }
+
+    /// Serves connections accepted from `incoming` until `shutdown` completes, running each
+    /// connection on a task started by `spawn`.
+    ///
+    /// This does not require a Tokio runtime, so can be driven by any executor. Connections only
+    /// need to implement Tokio's I/O traits, which can be provided for other runtimes' sockets by
+    /// a compatibility layer such as `async-compat`. The header read timeout and request timeouts
+    /// depend on Tokio's timer so are not applied, and no peer address is available to routes.
+    pub async fn serve_with<I>(
+        &self,
+        incoming: I,
+        spawn: impl Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static,
+        shutdown: impl Future<Output = ()>,
+    ) -> io::Result<()>
+    where
+        I: Accept + Unpin,
+        I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut config = self.config.clone();
+        config.request_timeouts.clear();
+        let service = WatcherService::new(
+            self.metrics_registry.clone(),
+            self.readiness_probe.clone(),
+            config,
+            self.http_metrics.clone(),
+        );
+
+        self.server_builder(incoming, self.config.server_limits.max_connections, None)
+            .executor(SpawnExecutor(Arc::new(spawn)))
+            .serve(make_service_fn(move |_: &LimitedConn<I::Conn>| {
+                let service = service.clone().into_layered();
+                async move { Ok::<_, Infallible>(service) }
+            }))
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(io::Error::other)
+    }
+
+    /// Starts a server on each address, none are started if any address fails to bind.
+    pub async fn start_servers(
+        &mut self,
+        addresses: Vec<SocketAddr>,
+    ) -> io::Result<Vec<(SocketAddr, JoinHandle<()>)>> {
+        let mut incomings = Vec::with_capacity(addresses.len());
+        {
+            let _runtime = self.enter_runtime();
+            for address in addresses {
+                incomings.push(bind(address, &self.config)?);
+            }
+        }
+
+        Ok(incomings
+            .into_iter()
+            .map(|incoming| {
+                let address = incoming.local_addr();
+                event!(trace, { %address, }, "Listening on {}", address);
+                (address, self.spawn_server(incoming))
+            })
+            .collect())
+    }
+
+    pub fn start_server_from_listener(
+        &mut self,
+        listener: std::net::TcpListener,
+    ) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+        listener.set_nonblocking(true)?;
+        let incoming = {
+            let _runtime = self.enter_runtime();
+            from_listener(TcpListener::from_std(listener)?)?
+        };
+        let address = incoming.local_addr();
+
+        event!(trace, { %address, }, "Listening on {} (provided listener)", address);
+        Ok((address, self.spawn_server(incoming)))
+    }
+
+    #[cfg(feature = "tls")]
+    pub async fn start_server_tls(
+        &mut self,
+        address: SocketAddr,
+        tls_config: rustls::ServerConfig,
+    ) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+        let incoming = {
+            let _runtime = self.enter_runtime();
+            bind(address, &self.config)?
+        };
+        let address = incoming.local_addr();
+        // Limited before the handshake so that clients which never complete one are still
+        // counted, and bounded by the header read timeout
+        let limits = &self.config.server_limits;
+        let incoming = TlsIncoming::new(
+            LimitedIncoming::new(incoming, limits.max_connections),
+            tls_config,
+            limits.header_read_timeout,
+        );
+
+        event!(trace, { %address, tls = true, }, "Listening on {} (TLS)", address);
+        Ok((address, self.spawn_server_with_limit(incoming, None)))
+    }
+
+    /// Serves on a Unix domain socket at `path`, replacing a stale socket left behind by a
+    /// previous run. The socket file is removed once the server stops.
+    #[cfg(all(unix, feature = "uds"))]
+    pub fn start_server_uds(&mut self, path: std::path::PathBuf) -> io::Result<JoinHandle<()>> {
+        let incoming = {
+            let _runtime = self.enter_runtime();
+            UnixIncoming::bind(path.clone())?
+        };
+
+        event!(trace, { path = %path.display(), }, "Listening on {}", path.display());
+        Ok(self.spawn_server(incoming))
+    }
+
+    /// Serves TLS, only accepting clients presenting a certificate signed by one of
+    /// `client_roots`. Custom route handlers can inspect the client's certificate via the
+    /// [`ClientCertificates`](crate::ClientCertificates) request extension.
+    #[cfg(feature = "tls")]
+    pub async fn start_server_mtls(
+        &mut self,
+        address: SocketAddr,
+        cert_chain: Vec<rustls::Certificate>,
+        key: rustls::PrivateKey,
+        client_roots: rustls::RootCertStore,
+    ) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+        let tls_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(client_roots).boxed(),
+            )
+            .with_single_cert(cert_chain, key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        self.start_server_tls(address, tls_config).await
+    }
+
+    // Sockets are driven by the runtime which is entered when they are created
+    fn enter_runtime(&self) -> Option<EnterGuard<'_>> {
+        self.config.runtime.as_ref().map(Handle::enter)
+    }
+
+    fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.config.runtime {
+            Some(runtime) => runtime.spawn(task),
+            None => tokio::spawn(task),
+        }
+    }
+
+    fn server_builder<I>(
+        &self,
+        incoming: I,
+        max_connections: Option<usize>,
+        header_read_timeout: Option<Duration>,
+    ) -> Builder<LimitedIncoming<I>> {
+        let limits = &self.config.server_limits;
+        let builder = Server::builder(LimitedIncoming::new(incoming, max_connections))
+            .http1_keepalive(limits.keep_alive);
+        #[cfg(feature = "http2")]
+        let builder = builder.http1_only(!self.config.http2);
+        let builder = match self.config.request_limits.max_buf_size {
+            // Hyper does not allow buffers smaller than this
+            Some(size) => builder.http1_max_buf_size(size.max(8192)),
+            None => builder,
+        };
+        match header_read_timeout {
+            Some(timeout) => builder.http1_header_read_timeout(timeout),
+            None => builder,
+        }
+    }
+
+    fn spawn_server<I>(&self, incoming: I) -> JoinHandle<()>
+    where
+        I: Accept + Send + Unpin + 'static,
+        I::Conn: Connection + AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.spawn_server_with_limit(incoming, self.config.server_limits.max_connections)
+    }
+
+    fn spawn_server_with_limit<I>(
+        &self,
+        incoming: I,
+        max_connections: Option<usize>,
+    ) -> JoinHandle<()>
+    where
+        I: Accept + Send + Unpin + 'static,
+        I::Conn: Connection + AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        // Subscribe before spawning so that a stop requested straight after starting is not missed
         let mut termination_signal = self.termination_signal.subscribe();
+        let mut timeout_signal = self.termination_signal.subscribe();
+        let shutdown_timeout = self.config.shutdown_timeout;
+        let service = self.service();
+        let server = self
+            .server_builder(
+                incoming,
+                max_connections,
+                self.config.server_limits.header_read_timeout,
+            )
+            .serve(make_service_fn(move |conn: &LimitedConn<I::Conn>| {
+                let service = service.clone().with_connection(conn.info()).into_layered();
+                async move { Ok::<_, Infallible>(service) }
+            }));
+        let running = RunningTask::new(self.running_tasks.clone());
 
-        tokio::spawn(async move {
-            let server =
-                Server::bind(&address).serve(Shared::new(service_fn(move |req: Request<Body>| {
-                    let registry = registry.clone();
-                    let readiness_conditions = readiness_conditions.clone();
-
-                    async move {
-                        Ok::<_, anyhow::Error>(match req.uri().path() {
-                            "/metrics" => {
-                                let mut buffer = String::new();
-                                encode(&mut buffer, &registry.read().unwrap())?;
-                                Response::builder()
-                                    .status(StatusCode::OK)
-                                    .header(CONTENT_TYPE, "text/plain")
-                                    .body(Body::from(buffer))
-                                    .unwrap()
-                            }
-                            "/ready" => {
-                                let ready = readiness_conditions.is_ready();
-                                Response::builder()
-                                    .status(match ready {
-                                        true => StatusCode::OK,
-                                        false => StatusCode::SERVICE_UNAVAILABLE,
-                                    })
-                                    .header(CONTENT_TYPE, "application/json")
-                                    .body(Body::from(
-                                        serde_json::to_string(
-                                            readiness_conditions.conditions.read().unwrap().deref(),
-                                        )
-                                        .unwrap(),
-                                    ))
-                                    .unwrap()
-                            }
-                            "/alive" => Response::builder()
-                                .status(StatusCode::OK)
-                                .header(CONTENT_TYPE, "text/plain")
-                                .body("alive".into())
-                                .unwrap(),
-                            _ => Response::builder()
-                                .status(StatusCode::NOT_FOUND)
-                                .header(CONTENT_TYPE, "text/plain")
-                                .body("Not found".into())
-                                .unwrap(),
-                        })
-                    }
-                })));
+        let server = async move {
+            let _running = running;
 
-            log::trace!("Listening on {}", address);
             let graceful = server.with_graceful_shutdown(async {
                 termination_signal.recv().await.ok();
             });
 
-            if let Err(e) = graceful.await {
-                log::error!("Error running server: {}", e);
+            let result = match shutdown_timeout {
+                Some(timeout) => tokio::select! {
+                    result = graceful => result,
+                    _ = async {
+                        timeout_signal.recv().await.ok();
+                        tokio::time::sleep(timeout).await;
+                    } => {
+                        event!(warn, { ?timeout, }, "Server did not shut down within {:?}, dropping connections", timeout);
+                        Ok(())
+                    }
+                },
+                None => graceful.await,
+            };
+
+            if let Err(e) = result {
+                event!(error, { error = %e, }, "Error running server: {}", e);
+            }
+        };
+
+        self.spawn(server)
+    }
+
+    /// Polls `check` every `interval`, marking `condition` ready or not ready according to its
+    /// result until the server is stopped.
+    pub fn start_readiness_check<F, Fut>(
+        &self,
+        condition: C,
+        interval: Duration,
+        check: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        let readiness_probe = self.readiness_probe.clone();
+        let mut termination_signal = self.termination_signal.subscribe();
+        let running = RunningTask::new(self.running_tasks.clone());
+
+        self.spawn(async move {
+            let _running = running;
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = termination_signal.recv() => break,
+                }
+
+                let ready = tokio::select! {
+                    ready = check() => ready,
+                    _ = termination_signal.recv() => break,
+                };
+
+                match ready {
+                    true => readiness_probe.mark_ready(condition.clone()),
+                    false => readiness_probe.mark_not_ready(condition.clone()),
+                }
             }
         })
     }
 
-    pub fn stop_server(&mut self) -> Result<usize, broadcast::error::SendError<()>> {
-        log::trace!("Requesting server shutdown");
-        self.termination_signal.send(())
+    /// Sends metrics to the StatsD agent at `agent` over UDP every `interval`, with a final send
+    /// once the server is stopped. Labels are sent as DogStatsD tags.
+    #[cfg(feature = "statsd")]
+    pub async fn start_statsd(
+        &mut self,
+        agent: SocketAddr,
+        interval: Duration,
+    ) -> io::Result<JoinHandle<()>> {
+        let local: SocketAddr = match agent {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = {
+            let _runtime = self.enter_runtime();
+            let socket = std::net::UdpSocket::bind(local)?;
+            socket.connect(agent)?;
+            socket.set_nonblocking(true)?;
+            tokio::net::UdpSocket::from_std(socket)?
+        };
+
+        let watcher = self.clone();
+        let mut termination_signal = self.termination_signal.subscribe();
+        let running = RunningTask::new(self.running_tasks.clone());
+
+        Ok(self.spawn(async move {
+            let _running = running;
+
+            let mut encoder = StatsdEncoder::default();
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = termination_signal.recv() => break,
+                }
+
+                if let Err(e) = watcher.send_statsd(&socket, &mut encoder).await {
+                    log::error!("Failed to send metrics to StatsD: {}", e);
+                }
+            }
+
+            if let Err(e) = watcher.send_statsd(&socket, &mut encoder).await {
+                log::error!("Failed to send final metrics to StatsD: {}", e);
+            }
+        }))
+    }
+
+    #[cfg(feature = "statsd")]
+    async fn send_statsd(
+        &self,
+        socket: &tokio::net::UdpSocket,
+        encoder: &mut StatsdEncoder,
+    ) -> Result<()> {
+        let lines = encoder.encode(&self.encode_metrics()?)?;
+        for datagram in statsd::datagrams(&lines) {
+            socket.send(datagram.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Pushes metrics to the Pushgateway at `url` every `interval`, with a final push once the
+    /// server is stopped.
+    #[cfg(feature = "push")]
+    pub fn start_pushgateway(
+        &mut self,
+        url: String,
+        job: String,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let watcher = self.clone();
+        let mut termination_signal = self.termination_signal.subscribe();
+        let running = RunningTask::new(self.running_tasks.clone());
+        let uri = format!(
+            "{}/metrics/job/{}",
+            url.trim_end_matches('/'),
+            encode_path_segment(&job)
+        );
+
+        self.spawn(async move {
+            let _running = running;
+
+            let client = hyper::Client::new();
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = termination_signal.recv() => break,
+                }
+
+                if let Err(e) = watcher.push_metrics(&client, &uri).await {
+                    log::error!("Failed to push metrics: {}", e);
+                }
+            }
+
+            if let Err(e) = watcher.push_metrics(&client, &uri).await {
+                log::error!("Failed to push final metrics: {}", e);
+            }
+        })
+    }
+
+    #[cfg(feature = "push")]
+    async fn push_metrics(
+        &self,
+        client: &hyper::Client<hyper::client::HttpConnector>,
+        uri: &str,
+    ) -> Result<()> {
+        let body = to_prometheus_text(self.encode_metrics()?.into());
+
+        let request = Request::post(uri)
+            .header(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+            .body(Body::from(body))?;
+        let response = client.request(request).await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Pushgateway responded with {}", response.status());
+        }
+        Ok(())
+    }
+
+    /// Stops the server on SIGTERM or SIGINT (Ctrl+C on non-Unix platforms).
+    ///
+    /// Once this is called, those signals no longer terminate the process by default, the
+    /// application is expected to exit once the returned task completes.
+    pub fn shutdown_on_signals(&self) -> JoinHandle<()> {
+        let termination_signal = self.termination_signal.clone();
+
+        self.spawn(async move {
+            wait_for_termination_signal().await;
+            event!(
+                trace,
+                {},
+                "Received termination signal, requesting server shutdown"
+            );
+            termination_signal.send(()).ok();
+        })
+    }
+
+    /// Signals every server and pusher started by this watcher (or any of its clones) to stop.
+    ///
+    /// Only those already running are affected, servers started afterwards keep running until
+    /// the next stop. This allows a server to be restarted, for example on a new address or with
+    /// a new certificate, though the previous one should be waited for (see
+    /// [`Watcher::stop_and_wait`]) before reusing its address.
+    ///
+    /// Returns the number of servers and pushers signalled, or [`StopError::NoServerRunning`] if
+    /// there were none.
+    pub fn stop_server(&mut self) -> Result<usize, StopError> {
+        event!(trace, {}, "Requesting server shutdown");
+        self.termination_signal
+            .send(())
+            .map_err(|_| StopError::NoServerRunning)
+    }
+
+    /// Stops all servers and pushers started by this watcher (or any of its clones) and waits for
+    /// them to finish, servers complete in-flight requests and release their listening sockets.
+    pub async fn stop_and_wait(&mut self) {
+        let mut running_tasks = self.running_tasks.subscribe();
+        self.stop_server().ok();
+        running_tasks.wait_for(|count| *count == 0).await.ok();
+    }
+}
+
+/// Stops the server it was returned for when dropped.
+///
+/// Shutdown is requested in the same way as [`Watcher::stop_server`], so this also stops any
+/// other servers and pushers started by the same watcher.
+#[cfg(feature = "server")]
+pub struct ServerGuard {
+    address: SocketAddr,
+    termination_signal: broadcast::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "server")]
+impl ServerGuard {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops the server and waits for it to finish.
+    pub async fn stop(mut self) {
+        self.termination_signal.send(()).ok();
+        if let Some(handle) = self.handle.take() {
+            handle.await.ok();
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            self.termination_signal.send(()).ok();
+        }
+    }
+}
+
+// Percent-encodes everything other than unreserved characters, so that `segment` stays a single
+// path segment
+#[cfg(feature = "push")]
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// Runs hyper's connection tasks with a spawn function supplied by the caller
+#[cfg(feature = "server")]
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[cfg(feature = "server")]
+#[derive(Clone)]
+struct SpawnExecutor(Arc<dyn Fn(Task) + Send + Sync>);
+
+#[cfg(feature = "server")]
+impl<F: Future<Output = ()> + Send + 'static> hyper::rt::Executor<F> for SpawnExecutor {
+    fn execute(&self, future: F) {
+        (self.0)(Box::pin(future))
+    }
+}
+
+// Counts a background task as running for as long as it is alive, including when the task is
+// aborted via its `JoinHandle`
+#[cfg(feature = "server")]
+struct RunningTask(Arc<watch::Sender<usize>>);
+
+#[cfg(feature = "server")]
+impl RunningTask {
+    fn new(running_tasks: Arc<watch::Sender<usize>>) -> Self {
+        running_tasks.send_modify(|count| *count += 1);
+        Self(running_tasks)
+    }
+}
+
+#[cfg(feature = "server")]
+impl Drop for RunningTask {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+#[cfg(all(unix, feature = "server"))]
+async fn wait_for_termination_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(mut terminate), Ok(mut interrupt)) => {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = interrupt.recv() => {}
+            }
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            log::warn!("Failed to listen for termination signals: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(all(not(unix), feature = "server"))]
+async fn wait_for_termination_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("Failed to listen for termination signals: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(feature = "server")]
+fn bind(address: SocketAddr, config: &Config) -> io::Result<AddrIncoming> {
+    if !config.reuse_address && !config.reuse_port {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        return from_listener(TcpListener::from_std(listener)?);
+    }
+
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(config.reuse_address)?;
+    #[cfg(target_os = "linux")]
+    socket.set_reuseport(config.reuse_port)?;
+    socket.bind(address)?;
+    from_listener(socket.listen(1024)?)
+}
+
+#[cfg(feature = "server")]
+fn from_listener(listener: TcpListener) -> io::Result<AddrIncoming> {
+    AddrIncoming::from_listener(listener).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "server")]
+    use crate::test_util::TestWatcher;
+    use crate::AlwaysReady;
+    #[cfg(feature = "server")]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Requests `path` directly over `stream`, for tests of the servers themselves, the rest use
+    // `TestWatcher`
+    #[cfg(feature = "server")]
+    async fn http_get(mut stream: impl AsyncRead + AsyncWrite + Unpin, path: &str) -> String {
+        let request =
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_set_paths() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        assert_eq!(watcher.config.paths.metrics, "/metrics");
+
+        watcher.set_metrics_path("/custom/metrics").unwrap();
+        assert_eq!(watcher.config.paths.metrics, "/custom/metrics");
+
+        assert!(watcher.set_ready_path("ready").is_err());
+        assert_eq!(watcher.config.paths.ready, "/ready");
+
+        assert!(watcher.set_alive_path("").is_err());
+        assert_eq!(watcher.config.paths.alive, "/alive");
+
+        assert!(watcher.set_alive_path("/custom/metrics").is_err());
+        assert_eq!(watcher.config.paths.alive, "/alive");
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_set_version_info() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        assert!(watcher.set_version_info(vec![]).is_err());
+        assert_eq!(watcher.config.paths.version, None);
+
+        watcher
+            .set_version_info(vec![("version".into(), "1.2.3".into())])
+            .unwrap();
+        assert_eq!(watcher.config.paths.version.as_deref(), Some("/version"));
+    }
+
+    #[test]
+    fn test_with_registry() {
+        let mut registry = Registry::default();
+        let gauge = Gauge::<i64>::default();
+        gauge.set(42);
+        registry.register("existing", "An existing metric", gauge);
+
+        let watcher = Watcher::<AlwaysReady>::with_registry(registry);
+
+        let buffer = encode_registry(&watcher.metrics_registry_read()).unwrap();
+        assert!(buffer.contains("existing 42\n"));
+        assert!(buffer.contains("up 1\n"));
+        assert_eq!(buffer.matches("\nprocess_start_time_seconds ").count(), 1);
+    }
+
+    #[test]
+    fn test_try_register() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        assert!(watcher
+            .try_register("ticks", "Some ticks", Gauge::<i64>::default())
+            .is_ok());
+        assert_eq!(
+            watcher.try_register("ticks", "Some ticks", Gauge::<i64>::default()),
+            Err(AlreadyRegistered("ticks".into()))
+        );
+        assert!(watcher
+            .clone()
+            .try_register(
+                "up",
+                "Clashes with a built in metric",
+                Gauge::<i64>::default()
+            )
+            .is_err());
+
+        watcher.register_build_info(vec![("version".into(), "1.0.0".into())]);
+        watcher.register_build_info(vec![("version".into(), "2.0.0".into())]);
+
+        let buffer = watcher.encode_metrics().unwrap();
+        assert_eq!(buffer.matches("\nticks ").count(), 1);
+        assert_eq!(buffer.matches("\nup ").count(), 1);
+        assert_eq!(buffer.matches("\nbuild_info{").count(), 1);
+    }
+
+    #[test]
+    fn test_with_prefix() {
+        let watcher = Watcher::<AlwaysReady>::with_prefix("myservice");
+        watcher.metrics_registry().register(
+            "ticks",
+            "Registered after construction",
+            Gauge::<i64>::default(),
+        );
+
+        let buffer = watcher.encode_metrics().unwrap();
+        assert!(buffer.contains("\nmyservice_up 1\n"));
+        assert!(buffer.contains("\nmyservice_ticks 0\n"));
+        assert!(!buffer.contains("\nup "));
+    }
+
+    #[test]
+    fn test_with_constant_labels() {
+        let watcher = Watcher::<AlwaysReady>::with_constant_labels(vec![
+            ("instance".into(), "a".into()),
+            ("region".into(), "eu".into()),
+        ]);
+        watcher.metrics_registry().register(
+            "late",
+            "Registered after construction",
+            Gauge::<i64>::default(),
+        );
+
+        let buffer = watcher.encode_metrics().unwrap();
+        assert!(buffer.contains("\nup{instance=\"a\",region=\"eu\"} 1\n"));
+        assert!(buffer.contains("\nlate{instance=\"a\",region=\"eu\"} 0\n"));
+    }
+
+    #[test]
+    fn test_metrics_registry_read() {
+        let watcher = Watcher::<AlwaysReady>::default();
+
+        let first = watcher.metrics_registry_read();
+        let second = watcher.metrics_registry_read();
+        let buffer = encode_registry(&first).unwrap();
+        assert!(buffer.contains("up 1\n"));
+        drop((first, second));
+
+        watcher.metrics_registry().register(
+            "late",
+            "Registered after reading",
+            Gauge::<i64>::default(),
+        );
+        assert!(watcher.encode_metrics().unwrap().contains("late 0\n"));
+    }
+
+    #[test]
+    fn test_encode_metrics() {
+        let watcher = Watcher::<AlwaysReady>::default();
+
+        let buffer = watcher.encode_metrics().unwrap();
+        assert!(buffer.contains("up 1\n"));
+        assert!(buffer.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_build_info() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.register_build_info(vec![
+            ("version".into(), "1.2.3".into()),
+            ("commit".into(), "abcd".into()),
+        ]);
+
+        let buffer = encode_registry(&watcher.metrics_registry_read()).unwrap();
+        assert!(buffer.contains("build_info{version=\"1.2.3\",commit=\"abcd\"} 1\n"));
+    }
+
+    #[test]
+    fn test_condition_metrics() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Database,
+            Cache,
+        }
+
+        let watcher = Watcher::<ReadinessConditions>::default();
+        watcher
+            .readiness_probe()
+            .mark_ready(ReadinessConditions::Cache);
+
+        let buffer = encode_registry(&watcher.metrics_registry_read()).unwrap();
+        assert!(buffer.contains("ready{condition=\"Database\"} 0\n"));
+        assert!(buffer.contains("ready{condition=\"Cache\"} 1\n"));
+        assert!(buffer.contains("\nreadiness_conditions_total 2\n"));
+        assert!(buffer.contains("\nreadiness_conditions_ready 1\n"));
+        assert!(buffer.contains("\nreadiness_condition_age_seconds{condition=\"Cache\"} "));
+    }
+
+    #[test]
+    fn test_shared_readiness_probe() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Database,
+        }
+
+        let watcher = Watcher::<ReadinessConditions>::default();
+        watcher
+            .shared_readiness_probe()
+            .set_many([(ReadinessConditions::Database, true)]);
+        assert!(watcher.readiness_probe().is_ready());
+
+        watcher.shared_readiness_probe().reset();
+        assert!(!watcher.readiness_probe().is_ready());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_metrics_etag() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_metrics_cache_ttl(Duration::from_secs(60));
+        let test = TestWatcher::start(watcher).await.unwrap();
+
+        let request = test.request("/metrics").body(Body::empty()).unwrap();
+        let response = test.send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[hyper::header::ETAG].clone();
+
+        let request = test
+            .request("/metrics")
+            .header(hyper::header::IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+        let response = test.send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_drain() {
+        let watcher = Watcher::<AlwaysReady>::default();
+        let test = TestWatcher::start(watcher.clone()).await.unwrap();
+
+        watcher.begin_drain();
+        assert!(!test.get_ready().await.unwrap());
+        assert_eq!(test.get("/alive").await.unwrap().0, StatusCode::OK);
+
+        watcher.end_drain();
+        assert!(test.get_ready().await.unwrap());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_server_ephemeral_port() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let (address, handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        assert_ne!(address.port(), 0);
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_server_address_in_use() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let (address, _handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let err = watcher.start_server(address).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_server_from_listener() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let expected = listener.local_addr().unwrap();
+
+        let (address, handle) = watcher.start_server_from_listener(listener).unwrap();
+        assert_eq!(address, expected);
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let response = http_get(stream, "/alive").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[cfg(all(unix, feature = "uds"))]
+    #[tokio::test]
+    async fn test_start_server_uds() {
+        let path = std::env::temp_dir().join(format!("kagiyama-test-{}.sock", std::process::id()));
+
+        // Leave a stale socket behind
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        let _handle = watcher.start_server_uds(path.clone()).unwrap();
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let response = http_get(stream, "/alive").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_and_wait().await;
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_readiness_check() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Database,
+        }
+
+        let mut watcher = Watcher::<ReadinessConditions>::default();
+        let healthy = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let healthy = healthy.clone();
+            watcher.start_readiness_check(
+                ReadinessConditions::Database,
+                Duration::from_millis(10),
+                move || {
+                    let healthy = healthy.clone();
+                    async move { healthy.load(Ordering::Relaxed) }
+                },
+            )
+        };
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(watcher.readiness_probe().is_ready());
+
+        healthy.store(false, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!watcher.readiness_probe().is_ready());
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[cfg(feature = "statsd")]
+    #[tokio::test]
+    async fn test_start_statsd() {
+        let agent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        let handle = watcher
+            .start_statsd(agent.local_addr().unwrap(), Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        let mut buffer = [0; 1500];
+        let len = agent.recv(&mut buffer).await.unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..len]).into_owned();
+        assert!(datagram.lines().any(|line| line == "up:1|g"));
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+        let len = agent.recv(&mut buffer).await.unwrap();
+        assert!(String::from_utf8_lossy(&buffer[..len]).contains("up:1|g"));
+    }
+
+    #[cfg(feature = "push")]
+    #[tokio::test]
+    async fn test_start_pushgateway() {
+        use hyper::service::{make_service_fn, service_fn};
+        use std::convert::Infallible;
+        use tokio::sync::mpsc;
+
+        let (pushes_tx, mut pushes) = mpsc::unbounded_channel();
+        let pushgateway =
+            Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service_fn(move |_| {
+                let pushes_tx = pushes_tx.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let pushes_tx = pushes_tx.clone();
+                        async move {
+                            let path = req.uri().path().to_owned();
+                            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                            pushes_tx.send((path, body)).unwrap();
+                            Ok::<_, Infallible>(Response::new(Body::empty()))
+                        }
+                    }))
+                }
+            }));
+        let url = format!("http://{}", pushgateway.local_addr());
+        tokio::spawn(pushgateway);
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        let requests = prometheus_client::metrics::exemplar::CounterWithExemplar::<
+            Vec<(String, String)>,
+        >::default();
+        requests.inc_by(1, Some(vec![("trace_id".into(), "abc123".into())]));
+        watcher
+            .metrics_registry()
+            .register("requests", "Requests", requests);
+        let _handle =
+            watcher.start_pushgateway(url, "test job/1".into(), Duration::from_secs(3600));
+
+        let (path, body) = pushes.recv().await.unwrap();
+        assert_eq!(path, "/metrics/job/test%20job%2F1");
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("up 1\n"));
+        assert!(body.contains("requests_total 1\n"));
+        assert!(!body.contains("trace_id"));
+        assert!(!body.contains("# EOF"));
+
+        watcher.stop_and_wait().await;
+        assert!(pushes.try_recv().is_ok());
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_start_server_mtls() {
+        use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+        use tokio_rustls::TlsConnector;
+
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = Certificate::from_params(ca_params).unwrap();
+        let server =
+            Certificate::from_params(CertificateParams::new(vec!["localhost".into()])).unwrap();
+        let client =
+            Certificate::from_params(CertificateParams::new(vec!["client".into()])).unwrap();
+
+        let signed = |cert: &Certificate| {
+            (
+                vec![rustls::Certificate(
+                    cert.serialize_der_with_signer(&ca).unwrap(),
+                )],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+        };
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.add_route("/whoami".into(), |req| {
+            let certificates = req.extensions().get::<crate::ClientCertificates>();
+            Response::new(Body::from(format!(
+                "certificates: {}",
+                certificates.map_or(0, |c| c.0.len())
+            )))
+        });
+
+        let (cert_chain, key) = signed(&server);
+        let (address, _handle) = watcher
+            .start_server_mtls(
+                "127.0.0.1:0".parse().unwrap(),
+                cert_chain,
+                key,
+                roots.clone(),
+            )
+            .await
+            .unwrap();
+
+        let request = |client_auth: Option<(Vec<rustls::Certificate>, rustls::PrivateKey)>| {
+            let builder = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots.clone());
+            let config = match client_auth {
+                Some((cert_chain, key)) => builder.with_client_auth_cert(cert_chain, key).unwrap(),
+                None => builder.with_no_client_auth(),
+            };
+
+            async move {
+                let stream = tokio::net::TcpStream::connect(address).await?;
+                let mut stream = TlsConnector::from(Arc::new(config))
+                    .connect("localhost".try_into().unwrap(), stream)
+                    .await?;
+                stream
+                    .write_all(
+                        b"GET /whoami HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    )
+                    .await?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await?;
+                Ok::<_, io::Error>(response)
+            }
+        };
+
+        let response = request(Some(signed(&client))).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("certificates: 1"));
+
+        let response = request(None).await;
+        assert!(!response.is_ok_and(|r| r.starts_with("HTTP/1.1")));
+
+        watcher.stop_and_wait().await;
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_start_server_tls_limits() {
+        use tokio_rustls::TlsConnector;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert_chain = vec![rustls::Certificate(cert.serialize_der().unwrap())];
+        let tls_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                cert_chain.clone(),
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_server_limits(ServerLimits {
+            max_connections: Some(1),
+            header_read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let (address, _) = watcher
+            .start_server_tls("127.0.0.1:0".parse().unwrap(), tls_config)
+            .await
+            .unwrap();
+        let closed = |mut stream: tokio::net::TcpStream| async move {
+            let mut buffer = Vec::new();
+            tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        // Never sends a ClientHello, so holds the only connection until the handshake times out
+        let idle = tokio::net::TcpStream::connect(address).await.unwrap();
+        let over_limit = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert_eq!(closed(over_limit).await, 0);
+        assert_eq!(closed(idle).await, 0);
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&cert_chain[0]).unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TlsConnector::from(Arc::new(client_config))
+            .connect(
+                "localhost".try_into().unwrap(),
+                tokio::net::TcpStream::connect(address).await.unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_and_wait().await;
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_serve() {
+        let watcher = Watcher::<AlwaysReady>::default();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            watcher
+                .serve(address, async {
+                    shutdown_signal.await.ok();
+                })
+                .await
+        });
+
+        let response = loop {
+            match tokio::net::TcpStream::connect(address).await {
+                Ok(stream) => break http_get(stream, "/alive").await,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_serve_with() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll, Wake, Waker},
+            thread::Thread,
+        };
+
+        // A minimal executor, so that nothing runs within a Tokio runtime
+        struct Unpark(Thread);
+
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+            let mut cx = Context::from_waker(&waker);
+            let mut future = std::pin::pin!(future);
+            loop {
+                match future.as_mut().poll(&mut cx) {
+                    Poll::Ready(output) => return output,
+                    Poll::Pending => std::thread::park(),
+                }
+            }
+        }
+
+        struct Incoming(tokio::sync::mpsc::UnboundedReceiver<tokio::io::DuplexStream>);
+
+        impl Accept for Incoming {
+            type Conn = tokio::io::DuplexStream;
+            type Error = Infallible;
+
+            fn poll_accept(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+                self.get_mut().0.poll_recv(cx).map(|conn| conn.map(Ok))
+            }
+        }
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.add_route("/runtime".into(), |_| {
+            let runtime = Handle::try_current().is_ok();
+            Response::new(Body::from(runtime.to_string()))
+        });
+
+        let (connections, incoming) = tokio::sync::mpsc::unbounded_channel();
+        let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+        let server = std::thread::spawn(move || {
+            block_on(watcher.serve_with(
+                Incoming(incoming),
+                |task| {
+                    std::thread::spawn(move || block_on(task));
+                },
+                async {
+                    shutdown_signal.await.ok();
+                },
+            ))
+        });
+
+        let (mut client, conn) = tokio::io::duplex(4096);
+        connections.send(conn).unwrap();
+        let response = block_on(async {
+            client
+                .write_all(b"GET /runtime HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            response
+        });
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nfalse"));
+
+        shutdown.send(()).unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_server_guard() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let guard = watcher
+            .start_server_guard("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let address = guard.local_addr();
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+        guard.stop().await;
+        assert!(tokio::net::TcpStream::connect(address).await.is_err());
+
+        let guard = watcher
+            .start_server_guard("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(5), watcher.stop_and_wait())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_start_servers() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let servers = watcher
+            .start_servers(vec![
+                "127.0.0.1:0".parse().unwrap(),
+                "127.0.0.1:0".parse().unwrap(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_ne!(servers[0].0, servers[1].0);
+
+        for (address, _) in &servers {
+            let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            assert!(http_get(stream, "/alive")
+                .await
+                .starts_with("HTTP/1.1 200 OK"));
+        }
+
+        watcher.stop_server().unwrap();
+        for (_, handle) in servers {
+            handle.await.unwrap();
+        }
+
+        let in_use = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let err = watcher
+            .start_servers(vec![
+                "127.0.0.1:0".parse().unwrap(),
+                in_use.local_addr().unwrap(),
+            ])
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_route_remote_addr() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.add_route("/peer".into(), |req| {
+            let remote_addr = req.extensions().get::<SocketAddr>().unwrap();
+            Response::new(Body::from(remote_addr.to_string()))
+        });
+
+        let (address, _) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        assert!(http_get(stream, "/peer")
+            .await
+            .ends_with(&format!("\r\n\r\n{local_addr}")));
+
+        watcher.stop_server().unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_set_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("metrics")
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_runtime(runtime.handle().clone());
+        watcher.add_route("/thread".into(), |_| {
+            let thread = std::thread::current();
+            Response::new(Body::from(thread.name().unwrap_or_default().to_owned()))
+        });
+
+        let test = TestWatcher::start(watcher).await.unwrap();
+        assert_eq!(test.get("/thread").await.unwrap().1, "metrics");
+
+        drop(test);
+        runtime.shutdown_background();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_set_runtime_background_tasks() {
+        #[derive(Clone, Serialize, PartialEq, Eq, Hash, strum_macros::EnumIter)]
+        enum ReadinessConditions {
+            Thread,
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("metrics")
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut watcher = Watcher::<ReadinessConditions>::default();
+        watcher.set_runtime(runtime.handle().clone());
+
+        let handle = watcher.start_readiness_check(
+            ReadinessConditions::Thread,
+            Duration::from_millis(10),
+            || async { std::thread::current().name() == Some("metrics") },
+        );
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(watcher.readiness_probe().is_ready());
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+        runtime.shutdown_background();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_request_limits() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_request_limits(RequestLimits {
+            max_buf_size: Some(0),
+            ..Default::default()
+        });
+        let (address, _) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "GET /alive HTTP/1.1\r\nHost: localhost\r\nX-Padding: {}\r\n\r\n",
+            "x".repeat(16 * 1024)
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        // Hyper responds before closing the connection, which may be reset as the rest of the
+        // request is left unread, so keep whatever arrived before the reset
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.ok();
+        assert!(response.starts_with(b"HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_server().unwrap();
+    }
+
+    #[cfg(feature = "http2")]
+    #[tokio::test]
+    async fn test_enable_http2() {
+        let client = hyper::Client::builder()
+            .http2_only(true)
+            .build_http::<Body>();
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        let (address, _) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let uri: hyper::Uri = format!("http://{address}/metrics").parse().unwrap();
+        assert!(client.get(uri).await.is_err());
+
+        watcher.enable_http2(true);
+        let (address, _) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let uri: hyper::Uri = format!("http://{address}/metrics").parse().unwrap();
+        let response = client.get(uri).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_server().unwrap();
+    }
+
+    #[cfg(all(target_os = "linux", feature = "server"))]
+    #[tokio::test]
+    async fn test_reuse_port() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_reuse_address(true);
+        watcher.set_reuse_port(true);
+
+        let (address, _) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let (second, _) = watcher.start_server(address).await.unwrap();
+        assert_eq!(second, address);
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+
+        watcher.set_reuse_port(false);
+        let err = watcher.start_server(address).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        watcher.stop_server().unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_server_limits() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_server_limits(ServerLimits {
+            max_connections: Some(1),
+            keep_alive: true,
+            header_read_timeout: Some(Duration::from_millis(500)),
+        });
+
+        let (address, handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        // Whether the server closes the connection within `timeout`, without sending anything
+        async fn closed_within(stream: &mut tokio::net::TcpStream, timeout: Duration) -> bool {
+            let mut buffer = Vec::new();
+            tokio::time::timeout(timeout, stream.read_to_end(&mut buffer))
+                .await
+                .is_ok_and(|read| read.unwrap() == 0)
+        }
+
+        // Kept alive after being served, so holds the only connection
+        let mut served = tokio::net::TcpStream::connect(address).await.unwrap();
+        served
+            .write_all(b"GET /alive HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"alive") {
+            let mut buffer = [0; 1024];
+            let read = served.read(&mut buffer).await.unwrap();
+            assert_ne!(read, 0);
+            response.extend_from_slice(&buffer[..read]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        // Over the connection limit so closed straight away, well before the header read timeout
+        let mut over_limit = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(closed_within(&mut over_limit, Duration::from_millis(200)).await);
+
+        drop(served);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // Within the limit so kept open, until closed by the header read timeout
+        let mut idle = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(!closed_within(&mut idle, Duration::from_millis(200)).await);
+        assert!(closed_within(&mut idle, Duration::from_secs(5)).await);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_with_layer() {
+        use hyper::header::HeaderValue;
+        use tower::util::MapResponseLayer;
+
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.with_layer(MapResponseLayer::new(|mut response: Response<Body>| {
+            response
+                .headers_mut()
+                .insert("x-layer", HeaderValue::from_static("inner"));
+            response
+        }));
+        watcher.with_layer(MapResponseLayer::new(|mut response: Response<Body>| {
+            response
+                .headers_mut()
+                .append("x-layer", HeaderValue::from_static("outer"));
+            response
+        }));
+
+        let test = TestWatcher::start(watcher).await.unwrap();
+        let request = test.request("/alive").body(Body::empty()).unwrap();
+        let response = test.send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let layers: Vec<_> = response.headers().get_all("x-layer").iter().collect();
+        assert_eq!(layers, ["inner", "outer"]);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_shutdown_timeout() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+        watcher.set_shutdown_timeout(Duration::from_millis(50));
+
+        let (address, _handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        // Hold a connection open part way through a request
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /alive HTTP/1.1\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        tokio::time::timeout(Duration::from_secs(5), watcher.stop_and_wait())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_stop_server_repeatedly() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let servers = watcher
+            .start_servers(vec!["127.0.0.1:0".parse().unwrap(); 3])
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            watcher.stop_server().unwrap();
+        }
+
+        for (_, handle) in servers {
+            handle.await.unwrap();
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_restart_server() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let (_, handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+
+        // Stopping with nothing running does not affect servers started later
+        assert_eq!(watcher.stop_server(), Err(StopError::NoServerRunning));
+
+        let (address, handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(http_get(stream, "/alive")
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+        assert!(!handle.is_finished());
+
+        watcher.stop_server().unwrap();
+        handle.await.unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_stop_and_wait() {
+        let mut watcher = Watcher::<AlwaysReady>::default();
+
+        let (address, _handle) = watcher
+            .start_server("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        watcher.stop_and_wait().await;
+
+        let (_, handle) = watcher.start_server(address).await.unwrap();
+        watcher.stop_and_wait().await;
+        handle.await.unwrap();
+
+        // Returns immediately when no server is running
+        watcher.stop_and_wait().await;
     }
 }